@@ -0,0 +1,562 @@
+//! Cellular automata on rectangular boards.
+//!
+//! A board is stepped forward by applying a rule to every cell at once, where
+//! the rule sees the cell and its neighbours as picked out by a
+//! [`Neighbourhood`].  What lies beyond the edges of the board is set by its
+//! [`Edges`].
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Boards with at least this many cells are stepped in parallel.
+const PARALLEL_CELLS: usize = 1 << 14;
+
+/// The state of a single cell.
+pub trait Cell: Copy + Eq + Hash + Send + Sync {}
+
+impl<T: Copy + Eq + Hash + Send + Sync> Cell for T {}
+
+/// What lies beyond the edges of a board.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Edges<T> {
+    /// Every cell beyond the edges is fixed in this state.
+    Bounded(T),
+    /// The edges wrap around, so the board has no edges at all.
+    Toroidal,
+    /// The board goes on forever, with every cell beyond the edges in this
+    /// state.  The board grows as the pattern on it does, and the background
+    /// steps forward like any other cell.
+    Infinite(T),
+}
+
+/// The cells a rule looks at, as offsets from the cell being updated.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Neighbourhood {
+    offsets: Vec<(i64, i64)>,
+}
+
+impl Neighbourhood {
+    /// A neighbourhood of any offsets, which are passed to the rule in the order
+    /// given.  The cell itself is only included if `(0, 0)` is.
+    #[must_use]
+    pub fn new(offsets: Vec<(i64, i64)>) -> Self {
+        Self { offsets }
+    }
+
+    /// The cells within `radius` steps in any direction, including diagonally,
+    /// but not the cell itself.
+    #[must_use]
+    pub fn moore(radius: i64) -> Self {
+        Self::new(
+            Self::square(radius)
+                .offsets
+                .into_iter()
+                .filter(|&offset| offset != (0, 0))
+                .collect(),
+        )
+    }
+
+    /// The cells within `radius` steps up, down, left and right, not including
+    /// the cell itself.
+    #[must_use]
+    pub fn von_neumann(radius: i64) -> Self {
+        Self::new(
+            Self::moore(radius)
+                .offsets
+                .into_iter()
+                .filter(|(dx, dy)| dx.abs() + dy.abs() <= radius)
+                .collect(),
+        )
+    }
+
+    /// The square of cells within `radius` steps in any direction, including
+    /// the cell itself, in reading order.
+    #[must_use]
+    pub fn square(radius: i64) -> Self {
+        Self::new(
+            (-radius..=radius)
+                .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                .collect(),
+        )
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// How far the neighbourhood reaches along either axis.
+    fn radius(&self) -> i64 {
+        self.offsets
+            .iter()
+            .map(|(dx, dy)| dx.abs().max(dy.abs()))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// A rectangular board of cells.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Board<T> {
+    width: usize,
+    height: usize,
+    /// Cells in row-major order.
+    cells: Vec<T>,
+    /// The coordinates of the top left cell, which only change as an infinite
+    /// board grows and shrinks.
+    origin: (i64, i64),
+    edges: Edges<T>,
+}
+
+impl<T: Cell> Board<T> {
+    /// Creates a board from cells given in row-major order, with its top left
+    /// cell at `(0, 0)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cells` doesn't have `width * height` entries, or if a
+    /// toroidal board is empty, since there would be nothing to wrap around to.
+    #[must_use]
+    pub fn new(width: usize, height: usize, cells: Vec<T>, edges: Edges<T>) -> Self {
+        assert_eq!(cells.len(), width * height, "board must be rectangular");
+        assert!(
+            edges != Edges::Toroidal || (width > 0 && height > 0),
+            "toroidal board must not be empty"
+        );
+
+        Self {
+            width,
+            height,
+            cells,
+            origin: (0, 0),
+            edges,
+        }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[must_use]
+    pub fn edges(&self) -> Edges<T> {
+        self.edges
+    }
+
+    /// The coordinates of the top left cell stored on the board.
+    #[must_use]
+    pub fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    /// The cells stored on the board in row-major order, starting from the
+    /// origin.
+    #[must_use]
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// The state of the cell at `(x, y)`, which may be beyond the edges.
+    #[must_use]
+    pub fn get(&self, x: i64, y: i64) -> T {
+        let (mut x, mut y) = (x - self.origin.0, y - self.origin.1);
+        let (width, height) = (self.width as i64, self.height as i64);
+
+        match self.edges {
+            Edges::Toroidal => {
+                x = x.rem_euclid(width);
+                y = y.rem_euclid(height);
+            }
+            Edges::Bounded(outside) | Edges::Infinite(outside) => {
+                if x < 0 || x >= width || y < 0 || y >= height {
+                    return outside;
+                }
+            }
+        }
+
+        self.cells[(y * width + x) as usize]
+    }
+
+    /// Counts the cells stored on the board which satisfy `predicate`.  On an
+    /// infinite board, the cells beyond the edges aren't counted.
+    pub fn count<P: Fn(T) -> bool>(&self, predicate: P) -> usize {
+        self.cells.iter().filter(|&&cell| predicate(cell)).count()
+    }
+
+    /// Draws the cells stored on the board, one row per line.
+    pub fn render<F: Fn(T) -> char>(&self, glyph: F) -> String {
+        self.cells
+            .chunks(self.width.max(1))
+            .map(|row| row.iter().map(|&cell| glyph(cell)).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Steps every cell forward at once.  `rule` is given each cell's current
+    /// state and the states of its neighbours, in the neighbourhood's order.
+    #[must_use]
+    pub fn step<R>(&self, neighbourhood: &Neighbourhood, rule: R) -> Self
+    where
+        R: Fn(T, &[T]) -> T + Sync,
+    {
+        // An infinite board has to grow by the reach of the neighbourhood, since
+        // cells that far beyond the edges could change.
+        let margin = match self.edges {
+            Edges::Infinite(_) => neighbourhood.radius(),
+            _ => 0,
+        };
+        let width = self.width + 2 * margin as usize;
+        let height = self.height + 2 * margin as usize;
+        let origin = (self.origin.0 - margin, self.origin.1 - margin);
+
+        let next = |index: usize, buffer: &mut Vec<T>| {
+            let x = origin.0 + (index % width) as i64;
+            let y = origin.1 + (index / width) as i64;
+
+            buffer.clear();
+            buffer.extend(
+                neighbourhood
+                    .offsets
+                    .iter()
+                    .map(|(dx, dy)| self.get(x + dx, y + dy)),
+            );
+            rule(self.get(x, y), buffer)
+        };
+
+        let size = width * height;
+        let cells: Vec<T> = if size >= PARALLEL_CELLS {
+            (0..size)
+                .into_par_iter()
+                .map_init(Vec::new, |buffer, index| next(index, buffer))
+                .collect()
+        } else {
+            let mut buffer = Vec::new();
+            (0..size).map(|index| next(index, &mut buffer)).collect()
+        };
+
+        let edges = match self.edges {
+            Edges::Infinite(background) => {
+                let neighbours = vec![background; neighbourhood.len()];
+                Edges::Infinite(rule(background, &neighbours))
+            }
+            edges => edges,
+        };
+
+        let board = Self {
+            width,
+            height,
+            cells,
+            origin,
+            edges,
+        };
+
+        match edges {
+            Edges::Infinite(background) => board.trim(background),
+            _ => board,
+        }
+    }
+
+    /// Shrinks an infinite board down to the cells which differ from the
+    /// background, so that it only grows as far as the pattern does.
+    fn trim(self, background: T) -> Self {
+        let differs = |x: usize, y: usize| self.cells[y * self.width + x] != background;
+
+        let rows: Vec<usize> = (0..self.height)
+            .filter(|&y| (0..self.width).any(|x| differs(x, y)))
+            .collect();
+        let cols: Vec<usize> = (0..self.width)
+            .filter(|&x| (0..self.height).any(|y| differs(x, y)))
+            .collect();
+
+        let (top, bottom, left, right) =
+            match (rows.first(), rows.last(), cols.first(), cols.last()) {
+                (Some(&top), Some(&bottom), Some(&left), Some(&right)) => {
+                    (top, bottom, left, right)
+                }
+                _ => {
+                    return Self {
+                        width: 0,
+                        height: 0,
+                        cells: Vec::new(),
+                        origin: (0, 0),
+                        edges: self.edges,
+                    }
+                }
+            };
+
+        let cells = (top..=bottom)
+            .flat_map(|y| (left..=right).map(move |x| (x, y)))
+            .map(|(x, y)| self.cells[y * self.width + x])
+            .collect();
+
+        Self {
+            width: right - left + 1,
+            height: bottom - top + 1,
+            cells,
+            origin: (self.origin.0 + left as i64, self.origin.1 + top as i64),
+            edges: self.edges,
+        }
+    }
+}
+
+/// A board settled into a repeating cycle of states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// The first generation of the cycle.
+    pub start: usize,
+    /// The number of generations before the board repeats.
+    pub period: usize,
+}
+
+/// A board together with the rule that steps it forward.
+#[derive(Clone, Debug)]
+pub struct Automaton<T, R> {
+    board: Board<T>,
+    neighbourhood: Neighbourhood,
+    rule: R,
+    generation: usize,
+}
+
+impl<T: Cell, R: Fn(T, &[T]) -> T + Sync> Automaton<T, R> {
+    #[must_use]
+    pub fn new(board: Board<T>, neighbourhood: Neighbourhood, rule: R) -> Self {
+        Self {
+            board,
+            neighbourhood,
+            rule,
+            generation: 0,
+        }
+    }
+
+    /// The board in the current generation.
+    #[must_use]
+    pub fn board(&self) -> &Board<T> {
+        &self.board
+    }
+
+    /// The number of steps taken so far.
+    #[must_use]
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn step(&mut self) {
+        self.board = self.board.step(&self.neighbourhood, &self.rule);
+        self.generation += 1;
+    }
+
+    /// Returns an iterator over every generation, starting with the current one.
+    pub fn generations(self) -> Generations<T, R> {
+        Generations {
+            automaton: self,
+            started: false,
+        }
+    }
+
+    /// Steps forward until the board is in a state it's been in before, giving
+    /// up after `limit` steps.  Generations are counted from the current one.
+    ///
+    /// A pattern which moves across an infinite board without changing doesn't
+    /// count as repeating, as it's in a different place each time.
+    pub fn find_cycle(&mut self, limit: usize) -> Option<Cycle> {
+        let mut seen = HashMap::from([(self.board.clone(), 0)]);
+
+        for step in 1..=limit {
+            self.step();
+            if let Some(&start) = seen.get(&self.board) {
+                return Some(Cycle {
+                    start,
+                    period: step - start,
+                });
+            }
+            seen.insert(self.board.clone(), step);
+        }
+
+        None
+    }
+}
+
+/// An iterator over the generations of an automaton.
+pub struct Generations<T, R> {
+    automaton: Automaton<T, R>,
+    started: bool,
+}
+
+impl<T: Cell, R: Fn(T, &[T]) -> T + Sync> Iterator for Generations<T, R> {
+    type Item = Board<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            self.automaton.step();
+        }
+        self.started = true;
+
+        Some(self.automaton.board.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life(cell: bool, neighbours: &[bool]) -> bool {
+        let alive = neighbours.iter().filter(|&&n| n).count();
+        alive == 3 || (cell && alive == 2)
+    }
+
+    fn board(rows: &[&str], edges: Edges<bool>) -> Board<bool> {
+        let cells = rows.iter().flat_map(|row| row.chars()).map(|c| c == '#');
+        Board::new(rows[0].len(), rows.len(), cells.collect(), edges)
+    }
+
+    fn render(board: &Board<bool>) -> String {
+        board.render(|alive| if alive { '#' } else { '.' })
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        assert_eq!(Neighbourhood::moore(1).len(), 8);
+        assert_eq!(Neighbourhood::von_neumann(1).len(), 4);
+        assert_eq!(Neighbourhood::square(1).len(), 9);
+        assert_eq!(Neighbourhood::square(1).offsets[0], (-1, -1));
+        assert_eq!(Neighbourhood::von_neumann(2).radius(), 2);
+    }
+
+    #[test]
+    fn blinker_on_a_torus_has_period_two() {
+        let blinker = board(
+            &[".....", ".....", ".###.", ".....", "....."],
+            Edges::Toroidal,
+        );
+        let mut automaton = Automaton::new(blinker, Neighbourhood::moore(1), life);
+
+        assert_eq!(
+            automaton.find_cycle(10),
+            Some(Cycle {
+                start: 0,
+                period: 2
+            })
+        );
+        assert_eq!(automaton.generation(), 2);
+    }
+
+    #[test]
+    fn edges_change_what_lies_beyond() {
+        let b = board(&["#..", "...", "..."], Edges::Bounded(false));
+        assert!(b.get(0, 0));
+        assert!(!b.get(3, 3));
+        assert!(!b.get(-1, -1));
+
+        let t = board(&["#..", "...", "..."], Edges::Toroidal);
+        assert!(t.get(3, 3));
+        assert!(t.get(-3, 0));
+
+        let i = board(&["..."], Edges::Infinite(true));
+        assert!(i.get(100, -100));
+    }
+
+    #[test]
+    fn empty_bounded_boards_are_all_outside() {
+        let b = Board::new(0, 3, vec![], Edges::Bounded(true));
+        assert!(b.get(0, 0));
+        assert_eq!(b.step(&Neighbourhood::moore(1), life), b);
+    }
+
+    #[test]
+    #[should_panic(expected = "toroidal board must not be empty")]
+    fn empty_toroidal_boards_are_rejected() {
+        let _ = Board::<bool>::new(3, 0, vec![], Edges::Toroidal);
+    }
+
+    #[test]
+    fn glider_on_an_infinite_board_moves_without_repeating() {
+        let glider = board(&[".#.", "..#", "###"], Edges::Infinite(false));
+        let mut generations =
+            Automaton::new(glider.clone(), Neighbourhood::moore(1), life).generations();
+
+        assert_eq!(generations.next().unwrap(), glider);
+
+        // After four generations the glider has the same shape, one cell down
+        // and to the right, and the board has been trimmed down to it.
+        let moved = generations.nth(3).unwrap();
+        assert_eq!(render(&moved), render(&glider));
+        assert_eq!((moved.width(), moved.height()), (3, 3));
+        assert_eq!(moved.origin(), (1, 1));
+
+        let mut automaton = Automaton::new(glider, Neighbourhood::moore(1), life);
+        assert_eq!(automaton.find_cycle(40), None);
+        assert_eq!(automaton.board().origin(), (10, 10));
+    }
+
+    #[test]
+    fn pattern_dying_out_leaves_an_empty_board() {
+        let lonely = board(&["#"], Edges::Infinite(false));
+        let next = lonely.step(&Neighbourhood::moore(1), life);
+
+        assert_eq!((next.width(), next.height()), (0, 0));
+        assert_eq!(next.edges(), Edges::Infinite(false));
+        assert!(!next.get(0, 0));
+    }
+
+    #[test]
+    fn background_of_an_infinite_board_steps_too() {
+        // Like Q20's enhancement algorithms: a dark cell with dark surroundings
+        // lights up, and a lit cell with lit surroundings goes dark.
+        let flip = |cell: bool, neighbours: &[bool]| {
+            let lit = neighbours.iter().filter(|&&n| n).count();
+            match lit {
+                0 => true,
+                9 => false,
+                _ => cell,
+            }
+        };
+        let dot = board(&["#"], Edges::Infinite(false));
+        let mut automaton = Automaton::new(dot, Neighbourhood::square(1), flip);
+
+        automaton.step();
+        assert_eq!(automaton.board().edges(), Edges::Infinite(true));
+        assert_eq!(render(automaton.board()), "...\n.#.\n...");
+        assert_eq!(automaton.board().origin(), (-1, -1));
+
+        automaton.step();
+        assert_eq!(automaton.board().edges(), Edges::Infinite(false));
+        assert_eq!(
+            render(automaton.board()),
+            "#####\n#...#\n#.#.#\n#...#\n#####"
+        );
+    }
+
+    #[test]
+    fn large_boards_step_the_same_in_parallel() {
+        let size = 160;
+        assert!(size * size >= PARALLEL_CELLS);
+        let cells: Vec<bool> = (0..size * size)
+            .map(|i| i % 7 == 0 || i % 11 == 0)
+            .collect();
+        let big = Board::new(size, size, cells, Edges::Toroidal);
+        let next = big.step(&Neighbourhood::moore(1), life);
+
+        let neighbourhood = Neighbourhood::moore(1);
+        for y in 0..size as i64 {
+            for x in 0..size as i64 {
+                let neighbours: Vec<bool> = neighbourhood
+                    .offsets
+                    .iter()
+                    .map(|(dx, dy)| big.get(x + dx, y + dy))
+                    .collect();
+                assert_eq!(next.get(x, y), life(big.get(x, y), &neighbours));
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// The outcome of validating a single line with a [`BracketValidator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// Every chunk that was opened has been closed.
+    Complete,
+    /// The line is valid so far, but some chunks are still open.  `completion`
+    /// is the sequence of closing characters that would complete the line.
+    Incomplete { completion: String },
+    /// The character at `column` closed a chunk it didn't open.  `expected` is
+    /// the character that would have closed the innermost open chunk, or `None`
+    /// if there were no open chunks.
+    Corrupted {
+        column: usize,
+        expected: Option<char>,
+        found: char,
+    },
+}
+
+impl Validation {
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Validation::Complete)
+    }
+
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, Validation::Incomplete { .. })
+    }
+
+    #[must_use]
+    pub fn is_corrupted(&self) -> bool {
+        matches!(self, Validation::Corrupted { .. })
+    }
+}
+
+/// Validates lines made up of nested chunks, where each chunk starts and ends
+/// with one of a configurable set of open/close pairs.
+#[derive(Clone, Debug)]
+pub struct BracketValidator {
+    closers: HashMap<char, char>,
+    openers: HashMap<char, char>,
+}
+
+impl BracketValidator {
+    /// Creates a validator from a list of `(open, close)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a character is used more than once across all of the pairs.
+    pub fn new<I: IntoIterator<Item = (char, char)>>(pairs: I) -> Self {
+        let mut closers = HashMap::new();
+        let mut openers = HashMap::new();
+
+        for (open, close) in pairs {
+            assert!(
+                open != close
+                    && !closers.contains_key(&open)
+                    && !closers.contains_key(&close)
+                    && !openers.contains_key(&open)
+                    && !openers.contains_key(&close),
+                "bracket characters must be unique"
+            );
+            closers.insert(open, close);
+            openers.insert(close, open);
+        }
+
+        Self { closers, openers }
+    }
+
+    /// Validates `line`, stopping at the first corrupted character.  Columns are
+    /// zero-based character offsets into `line`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `line` contains a character which isn't part of any
+    /// of the configured pairs.
+    pub fn validate(&self, line: &str) -> Result<Validation> {
+        let mut open = Vec::new();
+
+        for (column, c) in line.chars().enumerate() {
+            if let Some(&close) = self.closers.get(&c) {
+                open.push(close);
+            } else if self.openers.contains_key(&c) {
+                let expected = open.pop();
+                if expected != Some(c) {
+                    return Ok(Validation::Corrupted {
+                        column,
+                        expected,
+                        found: c,
+                    });
+                }
+            } else {
+                return Err(anyhow!("unrecognised character {} at column {}", c, column));
+            }
+        }
+
+        if open.is_empty() {
+            Ok(Validation::Complete)
+        } else {
+            Ok(Validation::Incomplete {
+                completion: open.into_iter().rev().collect(),
+            })
+        }
+    }
+}
+
+impl Default for BracketValidator {
+    /// A validator for the four pairs `()`, `[]`, `{}` and `<>`.
+    fn default() -> Self {
+        Self::new([('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_where_lines_go_wrong() {
+        let validator = BracketValidator::default();
+
+        assert_eq!(
+            validator.validate("([]{<>})").unwrap(),
+            Validation::Complete
+        );
+        assert_eq!(
+            validator.validate("[({(<(())[]>[[{[]{<()<>>").unwrap(),
+            Validation::Incomplete {
+                completion: "}}]])})]".to_string()
+            }
+        );
+        assert_eq!(
+            validator.validate("{([(<{}[<>[]}>{[]{[(<()>").unwrap(),
+            Validation::Corrupted {
+                column: 12,
+                expected: Some(']'),
+                found: '}'
+            }
+        );
+        assert_eq!(
+            validator.validate("())").unwrap(),
+            Validation::Corrupted {
+                column: 2,
+                expected: None,
+                found: ')'
+            }
+        );
+        assert!(validator.validate("(x)").is_err());
+    }
+
+    #[test]
+    fn pairs_are_configurable() {
+        let validator = BracketValidator::new([('/', '\\'), ('a', 'b')]);
+
+        assert!(validator.validate("/ab\\").unwrap().is_complete());
+        assert!(validator.validate("/a/").unwrap().is_incomplete());
+        assert!(validator.validate("/b").unwrap().is_corrupted());
+        assert!(validator.validate("()").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "bracket characters must be unique")]
+    fn pairs_must_not_share_characters() {
+        let _ = BracketValidator::new([('(', ')'), (')', ']')]);
+    }
+}
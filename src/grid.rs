@@ -1,5 +1,5 @@
 use crate::{FromProblemInput, FromProblemInputLine, ProblemInput};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use std::collections::{HashMap, HashSet};
 use std::ops::{Add, Sub};
 use std::str::FromStr;
@@ -142,6 +142,178 @@ impl Add for Position {
     }
 }
 
+/// A horizontal or vertical line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    /// The vertical line with this x coordinate.
+    X(i64),
+    /// The horizontal line with this y coordinate.
+    Y(i64),
+}
+
+impl Axis {
+    /// Reflects `pos` in this line.
+    #[must_use]
+    pub fn reflect(self, pos: Position) -> Position {
+        match self {
+            Axis::X(x) => Position::new(2 * x - pos.x, pos.y),
+            Axis::Y(y) => Position::new(pos.x, 2 * y - pos.y),
+        }
+    }
+
+    /// How far `pos` is past this line, which is negative if it's before the line.
+    fn offset(self, pos: Position) -> i64 {
+        match self {
+            Axis::X(x) => pos.x - x,
+            Axis::Y(y) => pos.y - y,
+        }
+    }
+}
+
+/// A sparse set of positions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PointSet {
+    points: HashSet<Position>,
+}
+
+impl PointSet {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, pos: Position) -> bool {
+        self.points.contains(&pos)
+    }
+
+    /// Adds `pos`, returning whether it wasn't already in the set.
+    pub fn insert(&mut self, pos: Position) -> bool {
+        self.points.insert(pos)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Position> + '_ {
+        self.points.iter().copied()
+    }
+
+    /// Returns the smallest and largest coordinates of any point, or `None` if
+    /// the set is empty.
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<(Position, Position)> {
+        let mut points = self.iter();
+        let first = points.next()?;
+
+        Some(points.fold((first, first), |(min, max), p| {
+            (
+                Position::new(min.x.min(p.x), min.y.min(p.y)),
+                Position::new(max.x.max(p.x), max.y.max(p.y)),
+            )
+        }))
+    }
+
+    /// Moves every point by `delta`.
+    #[must_use]
+    pub fn translate(&self, delta: Position) -> Self {
+        self.iter().map(|p| p + delta).collect()
+    }
+
+    /// Reflects every point in `axis`.
+    #[must_use]
+    pub fn reflect(&self, axis: Axis) -> Self {
+        self.iter().map(|p| axis.reflect(p)).collect()
+    }
+
+    /// Rotates every point about the origin by `quarter_turns` right angles, in
+    /// the same direction that turns [`Direction::Right`] into
+    /// [`Direction::Up`].  Negative turns go the other way.
+    #[must_use]
+    pub fn rotate(&self, quarter_turns: i64) -> Self {
+        self.iter()
+            .map(|p| match quarter_turns.rem_euclid(4) {
+                0 => p,
+                1 => Position::new(-p.y, p.x),
+                2 => Position::new(-p.x, -p.y),
+                _ => Position::new(p.y, -p.x),
+            })
+            .collect()
+    }
+
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        self.points.union(&other.points).copied().collect()
+    }
+
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.points.intersection(&other.points).copied().collect()
+    }
+
+    /// Folds the points past `axis` back over it, leaving the points before it
+    /// where they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any point lies on the fold line.
+    pub fn fold(&self, axis: Axis) -> Result<Self> {
+        self.iter()
+            .map(|p| match axis.offset(p) {
+                0 => bail!("can't fold along {:?}: {:?} is on the line", axis, p),
+                offset if offset > 0 => Ok(axis.reflect(p)),
+                _ => Ok(p),
+            })
+            .collect()
+    }
+
+    /// Draws the bounding box of the points with `lit` for each point and `blank`
+    /// everywhere else.  Rows are drawn with `y` increasing downwards.
+    #[must_use]
+    pub fn render(&self, lit: char, blank: char) -> String {
+        let (min, max) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return String::new(),
+        };
+
+        (min.y..=max.y)
+            .map(|y| {
+                (min.x..=max.x)
+                    .map(|x| {
+                        if self.contains(Position::new(x, y)) {
+                            lit
+                        } else {
+                            blank
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl FromIterator<Position> for PointSet {
+    fn from_iter<I: IntoIterator<Item = Position>>(iter: I) -> Self {
+        Self {
+            points: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<Position> for PointSet {
+    fn extend<I: IntoIterator<Item = Position>>(&mut self, iter: I) {
+        self.points.extend(iter);
+    }
+}
+
 pub trait GridVisitor {
     type Output;
 
@@ -284,3 +456,65 @@ impl FromProblemInput<'_> for (Vec<Movement>, Vec<Movement>) {
         (f, g)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coords: &[(i64, i64)]) -> PointSet {
+        coords.iter().map(|&(x, y)| Position::new(x, y)).collect()
+    }
+
+    #[test]
+    fn folds_reflect_points_past_the_line() {
+        let set = points(&[(0, 0), (1, 4), (2, 3), (0, 1)]);
+
+        assert_eq!(
+            set.fold(Axis::Y(2)).unwrap(),
+            points(&[(0, 0), (1, 0), (2, 1), (0, 1)])
+        );
+        assert_eq!(
+            points(&[(0, 0), (4, 1), (3, 2)]).fold(Axis::X(2)).unwrap(),
+            points(&[(0, 0), (0, 1), (1, 2)])
+        );
+        assert!(set.fold(Axis::X(1)).is_err());
+    }
+
+    #[test]
+    fn transforms() {
+        let set = points(&[(1, 0), (2, 3)]);
+
+        assert_eq!(
+            set.translate(Position::new(-1, 2)),
+            points(&[(0, 2), (1, 5)])
+        );
+        assert_eq!(set.reflect(Axis::X(0)), points(&[(-1, 0), (-2, 3)]));
+        assert_eq!(set.rotate(1), points(&[(0, 1), (-3, 2)]));
+        assert_eq!(set.rotate(-1), set.rotate(3));
+        assert_eq!(set.rotate(2).rotate(2), set);
+
+        let right = points(&[(0, 0)]).translate(Position::new(0, 0).go(Direction::Right));
+        let up = points(&[(0, 0)]).translate(Position::new(0, 0).go(Direction::Up));
+        assert_eq!(right.rotate(1), up);
+    }
+
+    #[test]
+    fn set_operations_and_bounds() {
+        let a = points(&[(0, 0), (1, 1)]);
+        let b = points(&[(1, 1), (-2, 5)]);
+
+        assert_eq!(a.union(&b).len(), 3);
+        assert_eq!(a.intersection(&b), points(&[(1, 1)]));
+        assert_eq!(
+            a.union(&b).bounding_box(),
+            Some((Position::new(-2, 0), Position::new(1, 5)))
+        );
+        assert_eq!(PointSet::new().bounding_box(), None);
+    }
+
+    #[test]
+    fn render_draws_the_bounding_box() {
+        assert_eq!(points(&[(5, 5), (7, 6)]).render('#', '.'), "#..\n..#");
+        assert_eq!(PointSet::new().render('#', '.'), "");
+    }
+}
@@ -1,5 +1,4 @@
 #![feature(map_first_last)]
-#![feature(drain_filter)]
 #![feature(array_windows)]
 #![feature(const_eval_limit)]
 #![const_eval_limit = "0"]
@@ -18,9 +17,18 @@ use petgraph::prelude::StableGraph;
 use petgraph::{EdgeType, Undirected};
 use regex::Regex;
 use std::path::Path;
+use visualise::Sink;
 
+pub mod automaton;
+pub mod brackets;
 pub mod grid;
+pub mod ocr;
 pub mod questions;
+pub mod space;
+#[cfg(test)]
+mod testing;
+pub mod visualise;
+pub mod window;
 
 pub trait FromProblemInput<'a> {
     fn from(lines: &'a ProblemInput) -> Self;
@@ -39,6 +47,18 @@ pub trait Solution: Send + Sync {
     fn part2(&self, _lines: &ProblemInput) -> String {
         String::new()
     }
+
+    /// Solves part 1 while showing its working to `sink`.  Solutions with
+    /// nothing to show just solve it.
+    fn part1_visualised(&self, lines: &ProblemInput, _sink: &dyn Sink) -> String {
+        self.part1(lines)
+    }
+
+    /// Solves part 2 while showing its working to `sink`.  Solutions with
+    /// nothing to show just solve it.
+    fn part2_visualised(&self, lines: &ProblemInput, _sink: &dyn Sink) -> String {
+        self.part2(lines)
+    }
 }
 
 #[must_use]
@@ -95,6 +115,22 @@ impl ProblemInput {
         self.lines.iter().map(String::as_str)
     }
 
+    /// Lazily parses every number in the input, line by line, in the same way as
+    /// parsing into a `Vec<i64>`.
+    ///
+    /// # Panics
+    ///
+    /// The iterator panics when it reaches a number too large for an `i64`.
+    pub fn numbers(&self) -> impl Iterator<Item = i64> + '_ {
+        self.iter().flat_map(|line| {
+            number_regex().find_iter(line).map(|m| {
+                m.as_str()
+                    .parse::<i64>()
+                    .unwrap_or_else(|_| panic!("{} doesn't fit in an i64", m.as_str()))
+            })
+        })
+    }
+
     #[must_use]
     pub fn len(&self) -> usize {
         self.lines.len()
@@ -1,38 +1,101 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use aoc2021::questions::*;
+use aoc2021::visualise::{Discard, Dump, Player, Sink};
 use aoc2021::{ProblemInput, Solution};
 use crossbeam::thread;
+use std::path::PathBuf;
+use std::time::Duration;
 
-fn run_problem(solutions: &'static [Box<dyn Solution>], index: usize) -> (String, String) {
-    let (part1, part2) = thread::scope(move |s| {
-        let solution = &solutions[index];
+/// Where to send the frames solutions draw while they run.
+#[derive(Clone, Debug)]
+enum Visualisation {
+    Off,
+    /// Play frames in the terminal, pausing between each.
+    Play(Duration),
+    /// Write frames to files in a directory.
+    Dump(PathBuf),
+}
 
-        let part1 = s
-            .spawn(move |_| {
-                let path = format!("data/q{}.txt", index + 1);
-                let problem_input = ProblemInput::new(path).unwrap();
+impl Visualisation {
+    /// Reads `--play <milliseconds>` or `--dump <directory>` from the command
+    /// line.
+    fn from_args() -> Result<Self> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
 
-                solution.part1(&problem_input)
-            })
-            .join();
+        match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [] => Ok(Visualisation::Off),
+            ["--play", millis] => {
+                let millis = millis
+                    .parse()
+                    .with_context(|| format!("invalid frame delay {:?}", millis))?;
+                Ok(Visualisation::Play(Duration::from_millis(millis)))
+            }
+            ["--dump", dir] => Ok(Visualisation::Dump(PathBuf::from(dir))),
+            _ => bail!("usage: aoc2021 [--play <milliseconds> | --dump <directory>]"),
+        }
+    }
 
-        let part2 = s
-            .spawn(move |_| {
-                let path = format!("data/q{}.txt", index + 1);
-                let problem_input = ProblemInput::new(path).unwrap();
+    /// Solves one part of a problem, sending its frames wherever they should go.
+    fn solve(&self, solution: &dyn Solution, part: usize, input: &ProblemInput) -> Result<String> {
+        let solve = |sink: &dyn Sink| match part {
+            1 => solution.part1_visualised(input, sink),
+            _ => solution.part2_visualised(input, sink),
+        };
 
-                solution.part2(&problem_input)
-            })
-            .join();
+        match self {
+            Visualisation::Off => Ok(solve(&Discard)),
+            Visualisation::Play(delay) => Ok(solve(&Player::new(format!("Part {}", part), *delay))),
+            Visualisation::Dump(dir) => {
+                let dump = Dump::new(dir, format!("part{}", part))?;
+                let answer = solve(&dump);
+                let frames = dump.finish()?;
+                eprintln!(
+                    "Part {}: wrote {} frames to {}",
+                    part,
+                    frames,
+                    dir.display()
+                );
 
-        (part1, part2)
+                Ok(answer)
+            }
+        }
+    }
+}
+
+fn run_problem(
+    solutions: &'static [Box<dyn Solution>],
+    index: usize,
+    visualisation: &Visualisation,
+) -> Result<(String, String)> {
+    let (part1, part2) = thread::scope(move |s| {
+        let solution = &solutions[index];
+        let solve = move |part| {
+            let path = format!("data/q{}.txt", index + 1);
+            let problem_input = ProblemInput::new(path).unwrap();
+
+            visualisation.solve(solution.as_ref(), part, &problem_input)
+        };
+
+        if let Visualisation::Play(_) = visualisation {
+            // Both parts would draw over each other in the terminal, so play
+            // them one after the other.
+            let part1 = s.spawn(move |_| solve(1)).join();
+            let part2 = s.spawn(move |_| solve(2)).join();
+            (part1, part2)
+        } else {
+            let part1 = s.spawn(move |_| solve(1));
+            let part2 = s.spawn(move |_| solve(2));
+            (part1.join(), part2.join())
+        }
     })
     .unwrap();
 
-    (part1.unwrap(), part2.unwrap())
+    Ok((part1.unwrap()?, part2.unwrap()?))
 }
 
 fn main() -> Result<()> {
+    let visualisation = Visualisation::from_args()?;
+
     let solutions: Vec<Box<dyn Solution>> = vec![
         Box::new(Q1 {}),
         Box::new(Q2 {}),
@@ -69,7 +132,7 @@ fn main() -> Result<()> {
         let index = index - 1;
 
         if index < solutions.len() {
-            let (part1, part2) = run_problem(solutions, index);
+            let (part1, part2) = run_problem(solutions, index, &visualisation)?;
 
             println!("Part 1: {}", part1);
             println!("Part 2: {}", part2);
@@ -0,0 +1,438 @@
+//! Reading the block letters which some puzzles draw as their answer.
+//!
+//! Advent of Code uses two fonts: a small one six pixels tall, where most letters
+//! are four pixels wide, and a large one ten pixels tall, where most letters are
+//! six pixels wide.  Letters are always separated by at least one blank column,
+//! so an image is split into glyphs at its blank columns and each glyph is
+//! looked up in the font matching the image's height.
+
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// The glyphs of the small font, drawn with `#` for lit pixels.
+const SMALL: &[(char, &[&str])] = &[
+    ('A', &[".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', &["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', &[".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', &["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', &["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', &[".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', &["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', &["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', &["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', &["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', &["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', &[".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', &["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', &["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', &[".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', &["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', &["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', &["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// The glyphs of the large font, drawn with `#` for lit pixels.
+const LARGE: &[(char, &[&str])] = &[
+    (
+        'A',
+        &[
+            "..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'B',
+        &[
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#",
+            "#....#", "#####.",
+        ],
+    ),
+    (
+        'C',
+        &[
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#....#", ".####.",
+        ],
+    ),
+    (
+        'E',
+        &[
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'F',
+        &[
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'G',
+        &[
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#",
+            "#...##", ".###.#",
+        ],
+    ),
+    (
+        'H',
+        &[
+            "#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'J',
+        &[
+            "...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.",
+            "#...#.", ".###..",
+        ],
+    ),
+    (
+        'K',
+        &[
+            "#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..",
+            "#...#.", "#....#",
+        ],
+    ),
+    (
+        'L',
+        &[
+            "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'N',
+        &[
+            "#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##",
+            "#...##", "#....#",
+        ],
+    ),
+    (
+        'P',
+        &[
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'R',
+        &[
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'X',
+        &[
+            "#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'Z',
+        &[
+            "######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+];
+
+/// One of the fonts used to draw answers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Font {
+    /// Six pixels tall.
+    Small,
+    /// Ten pixels tall.
+    Large,
+}
+
+impl Font {
+    #[must_use]
+    pub fn height(self) -> usize {
+        match self {
+            Font::Small => 6,
+            Font::Large => 10,
+        }
+    }
+
+    /// Finds the font whose letters are `height` pixels tall.
+    #[must_use]
+    pub fn with_height(height: usize) -> Option<Self> {
+        [Font::Small, Font::Large]
+            .into_iter()
+            .find(|font| font.height() == height)
+    }
+
+    fn glyphs(self) -> &'static [(char, &'static [&'static str])] {
+        match self {
+            Font::Small => SMALL,
+            Font::Large => LARGE,
+        }
+    }
+
+    /// Looks up the letter drawn by `glyph`, whose rows are this font's height
+    /// and which has no blank columns at either side.
+    fn recognise(self, glyph: &[Vec<bool>]) -> Option<char> {
+        self.glyphs().iter().find_map(|&(letter, rows)| {
+            let matches = rows.iter().zip(glyph).all(|(expected, row)| {
+                expected.len() == row.len()
+                    && expected.chars().zip(row).all(|(c, &lit)| (c == '#') == lit)
+            });
+
+            if matches {
+                Some(letter)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// A glyph which didn't match any letter in the font.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownGlyph {
+    /// The position of the glyph among all the glyphs read.
+    pub index: usize,
+    /// The glyph itself, drawn with `#` and `.`.
+    pub rendering: String,
+}
+
+impl fmt::Display for UnknownGlyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "unrecognised glyph {}:", self.index)?;
+        write!(f, "{}", self.rendering)
+    }
+}
+
+/// The letters read from an image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reading {
+    /// The font the image was drawn in.
+    pub font: Font,
+    /// The letters read, with `?` in place of any glyph that wasn't recognised.
+    pub text: String,
+    /// Every glyph that wasn't recognised, in order.
+    pub unrecognised: Vec<UnknownGlyph>,
+}
+
+impl Reading {
+    /// Was every glyph recognised?
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.unrecognised.is_empty()
+    }
+
+    /// Returns the letters read, as long as every glyph was recognised.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error showing the first glyph that wasn't recognised.
+    pub fn into_text(self) -> Result<String> {
+        match self.unrecognised.first() {
+            Some(glyph) => bail!("{}", glyph),
+            None => Ok(self.text),
+        }
+    }
+}
+
+/// Reads the letters from a rendered image, where `#` and `█` are lit pixels and
+/// any other character is blank.
+///
+/// # Errors
+///
+/// Returns an error if nothing is lit, or if the lit pixels aren't the height of
+/// either font.
+pub fn read_grid(grid: &str) -> Result<Reading> {
+    let image = grid
+        .lines()
+        .map(|line| line.chars().map(|c| c == '#' || c == '█').collect())
+        .collect();
+
+    read(image)
+}
+
+/// Reads the letters drawn by a set of lit `(x, y)` points, with `y` increasing
+/// downwards.  The points can be anywhere, as only their positions relative to
+/// each other matter.
+///
+/// # Errors
+///
+/// Returns an error if there are no points, or if they aren't the height of
+/// either font.
+pub fn read_points<I: IntoIterator<Item = (i64, i64)>>(points: I) -> Result<Reading> {
+    let points: Vec<_> = points.into_iter().collect();
+    if points.is_empty() {
+        bail!("there are no letters to read");
+    }
+
+    let min_x = points.iter().map(|&(x, _)| x).min().unwrap();
+    let max_x = points.iter().map(|&(x, _)| x).max().unwrap();
+    let min_y = points.iter().map(|&(_, y)| y).min().unwrap();
+    let max_y = points.iter().map(|&(_, y)| y).max().unwrap();
+
+    let mut image = vec![vec![false; (max_x - min_x + 1) as usize]; (max_y - min_y + 1) as usize];
+    for (x, y) in points {
+        image[(y - min_y) as usize][(x - min_x) as usize] = true;
+    }
+
+    read(image)
+}
+
+/// Reads the letters from an image given as rows of pixels.
+fn read(mut image: Vec<Vec<bool>>) -> Result<Reading> {
+    // Crop away any blank rows above and below the letters.
+    image.retain(|row| row.contains(&true));
+    if image.is_empty() {
+        bail!("there are no letters to read");
+    }
+
+    let height = image.len();
+    let font = match Font::with_height(height) {
+        Some(font) => font,
+        None => bail!("letters are {} pixels tall, which matches no font", height),
+    };
+
+    let width = image.iter().map(Vec::len).max().unwrap();
+    let lit = |x: usize| image.iter().any(|row| row.get(x) == Some(&true));
+
+    let mut text = String::new();
+    let mut unrecognised = Vec::new();
+
+    let mut x = 0;
+    while x < width {
+        if !lit(x) {
+            x += 1;
+            continue;
+        }
+
+        // Each glyph runs until the next blank column.
+        let start = x;
+        while x < width && lit(x) {
+            x += 1;
+        }
+        let glyph: Vec<Vec<bool>> = image
+            .iter()
+            .map(|row| (start..x).map(|x| row.get(x) == Some(&true)).collect())
+            .collect();
+
+        if let Some(letter) = font.recognise(&glyph) {
+            text.push(letter);
+        } else {
+            unrecognised.push(UnknownGlyph {
+                index: text.chars().count(),
+                rendering: render(&glyph),
+            });
+            text.push('?');
+        }
+    }
+
+    Ok(Reading {
+        font,
+        text,
+        unrecognised,
+    })
+}
+
+fn render(glyph: &[Vec<bool>]) -> String {
+    glyph
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&lit| if lit { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws `text` in `font`, with a blank column between letters.
+    fn draw(font: Font, text: &str) -> String {
+        let glyphs: Vec<&[&str]> = text
+            .chars()
+            .map(|letter| font.glyphs().iter().find(|&&(c, _)| c == letter).unwrap().1)
+            .collect();
+
+        (0..font.height())
+            .map(|row| {
+                glyphs
+                    .iter()
+                    .map(|glyph| glyph[row])
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn reads_every_letter_of_both_fonts() {
+        for font in [Font::Small, Font::Large] {
+            let alphabet: String = font.glyphs().iter().map(|&(c, _)| c).collect();
+            let reading = read_grid(&draw(font, &alphabet)).unwrap();
+
+            assert_eq!(reading.font, font);
+            assert_eq!(reading.into_text().unwrap(), alphabet);
+        }
+    }
+
+    #[test]
+    fn reads_points_anywhere() {
+        let image = draw(Font::Small, "HI");
+        let points = image.lines().enumerate().flat_map(|(y, row)| {
+            row.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(x, _)| (x as i64 - 100, y as i64 + 7))
+        });
+
+        assert_eq!(read_points(points).unwrap().into_text().unwrap(), "HI");
+    }
+
+    #[test]
+    fn blank_rows_are_cropped() {
+        let image = format!(
+            "\n....\n{}\n....",
+            draw(Font::Small, "OK").replace('#', "█")
+        );
+        assert_eq!(read_grid(&image).unwrap().text, "OK");
+    }
+
+    #[test]
+    fn unknown_glyphs_are_reported() {
+        let image = draw(Font::Small, "AB")
+            .lines()
+            .map(|row| format!("{}..##", row))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let reading = read_grid(&image).unwrap();
+
+        assert_eq!(reading.text, "AB?");
+        assert!(!reading.is_complete());
+        assert_eq!(
+            reading.unrecognised,
+            [UnknownGlyph {
+                index: 2,
+                rendering: ["##"; 6].join("\n"),
+            }]
+        );
+        assert!(reading
+            .into_text()
+            .unwrap_err()
+            .to_string()
+            .starts_with("unrecognised glyph 2:"));
+    }
+
+    #[test]
+    fn images_must_match_a_font() {
+        assert!(read_grid("....\n....").is_err());
+        assert!(read_points(Vec::new()).is_err());
+        assert!(read_grid("#\n#\n#").is_err());
+    }
+}
@@ -1,32 +1,30 @@
+use crate::window::count_window_increases;
 use crate::{ProblemInput, Solution};
-use itertools::Itertools;
 
 pub struct Q1;
 
 impl Solution for Q1 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let nums: Vec<i64> = lines.parse();
-
-        nums.into_iter()
-            .tuple_windows()
-            .filter(|(prev, curr)| curr > prev)
-            .count()
-            .to_string()
+        count_window_increases(lines.numbers(), 1).to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let nums: Vec<i64> = lines.parse();
+        count_window_increases(lines.numbers(), 3).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 10] = [
+        "199", "200", "208", "210", "200", "207", "240", "269", "260", "263",
+    ];
 
-        nums.into_iter()
-            .tuple_windows()
-            // Using the problem terminology our variables refer to:
-            //
-            // 199 | a
-            // 200 | _ _
-            // 208 | _ _
-            // 210 |   d
-            .filter(|(a, _, _, d)| d > a)
-            .count()
-            .to_string()
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q1.part1(&lines), "7");
+        assert_eq!(Q1.part2(&lines), "5");
     }
 }
@@ -1,18 +1,9 @@
+use crate::brackets::{BracketValidator, Validation};
 use crate::{ProblemInput, Solution};
 use itertools::Itertools;
 
 pub struct Q10;
 
-fn left_to_right(c: char) -> char {
-    match c {
-        '(' => ')',
-        '{' => '}',
-        '[' => ']',
-        '<' => '>',
-        _ => panic!(),
-    }
-}
-
 fn illegal_score(c: char) -> i64 {
     match c {
         ')' => 3,
@@ -33,49 +24,37 @@ fn autocomplete_score(c: char) -> i64 {
     }
 }
 
-/// Returns a bool indicating whether processing the given character corrupted the chunk.
-fn chunk_process(chunk: &mut Vec<char>, c: char) -> bool {
-    if ['(', '{', '[', '<'].contains(&c) {
-        chunk.push(c);
-        return true;
-    }
-
-    if let Some(last_open) = chunk.pop() {
-        left_to_right(last_open) == c
-    } else {
-        false
-    }
+fn validate(lines: &ProblemInput) -> Vec<Validation> {
+    let validator = BracketValidator::default();
+    lines
+        .iter()
+        .map(|line| validator.validate(line).unwrap())
+        .collect()
 }
 
 impl Solution for Q10 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        lines
-            .lines
-            .iter()
-            .filter_map(|line| {
-                let mut chunk = Vec::new();
-                line.chars()
-                    .find(|c| !chunk_process(&mut chunk, *c))
-                    .map(illegal_score)
+        validate(lines)
+            .into_iter()
+            .filter_map(|v| match v {
+                Validation::Corrupted { found, .. } => Some(illegal_score(found)),
+                _ => None,
             })
             .sum::<i64>()
             .to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let scores: Vec<_> = lines
-            .lines
-            .iter()
-            .filter_map(|line| {
-                let mut chunk = Vec::new();
-                line.chars()
-                    .all(|c| chunk_process(&mut chunk, c))
-                    .then(|| chunk)
+        let scores: Vec<_> = validate(lines)
+            .into_iter()
+            .filter_map(|v| match v {
+                Validation::Incomplete { completion } => Some(completion),
+                _ => None,
             })
-            .map(|chunk| {
-                chunk.into_iter().rev().fold(0, |score, c| {
-                    (score * 5) + autocomplete_score(left_to_right(c))
-                })
+            .map(|completion| {
+                completion
+                    .chars()
+                    .fold(0, |score, c| (score * 5) + autocomplete_score(c))
             })
             .sorted()
             .collect();
@@ -83,3 +62,28 @@ impl Solution for Q10 {
         scores[scores.len() / 2].to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 10] = [
+        "[({(<(())[]>[[{[]{<()<>>",
+        "[(()[<>])]({[<{<<[]>>(",
+        "{([(<{}[<>[]}>{[]{[(<()>",
+        "(((({<>}<{<{<>}{[]{[]{}",
+        "[[<[([]))<([[{}[[()]]]",
+        "[{[{({}]{}}([{[{{{}}([]",
+        "{<[[]]>}<{[{[{[]{()[[[]",
+        "[<(<(<(<{}))><([]([]()",
+        "<{([([[(<>()){}]>(<<{{",
+        "<{([{{}}[<[[[<>{}]]]>[]]",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q10.part1(&lines), "26397");
+        assert_eq!(Q10.part2(&lines), "288957");
+    }
+}
@@ -1,87 +1,542 @@
+use crate::visualise::{Discard, Frame, Sink};
 use crate::{FromProblemInput, ProblemInput, Solution};
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
 pub struct Q11;
 
-#[derive(Debug)]
-struct Grid {
-    levels: HashMap<(i64, i64), i64>,
+#[derive(Debug, Clone)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    /// Energy levels in row-major order.
+    levels: Vec<u8>,
+    /// Whether the edges wrap around, so every octopus has eight neighbours.
+    toroidal: bool,
 }
 
 impl Grid {
+    /// Creates a grid from energy levels given in row-major order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `levels` doesn't have `width * height` entries.
+    #[must_use]
+    pub fn new(width: usize, height: usize, levels: Vec<u8>) -> Self {
+        assert_eq!(levels.len(), width * height, "grid must be rectangular");
+
+        Self {
+            width,
+            height,
+            levels,
+            toroidal: false,
+        }
+    }
+
+    /// Sets whether the edges of the grid wrap around.
+    #[must_use]
+    pub fn with_toroidal(mut self, toroidal: bool) -> Self {
+        self.toroidal = toroidal;
+        self
+    }
+
     fn size(&self) -> usize {
         self.levels.len()
     }
 
-    fn step(&mut self) -> usize {
-        // Energy level of each octopus increases by one
-        for v in self.levels.values_mut() {
-            *v += 1;
+    /// Hashes the energy levels, for spotting states that might have been seen
+    /// before without keeping a copy of each one.
+    fn key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.levels.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the distinct neighbours of the octopus at `index`, not including
+    /// itself.
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (x, y) = ((index % self.width) as i64, (index / self.width) as i64);
+        let (width, height) = (self.width as i64, self.height as i64);
+
+        let mut neighbours = [0; 8];
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (mut nx, mut ny) = (x + dx, y + dy);
+                if self.toroidal {
+                    nx = nx.rem_euclid(width);
+                    ny = ny.rem_euclid(height);
+                } else if nx < 0 || nx >= width || ny < 0 || ny >= height {
+                    continue;
+                }
+
+                // Tiny toroidal grids can reach the same octopus more than once.
+                let neighbour = (ny * width + nx) as usize;
+                if neighbour != index && !neighbours[..count].contains(&neighbour) {
+                    neighbours[count] = neighbour;
+                    count += 1;
+                }
+            }
         }
 
-        // Flashing
-        let mut already_flashed = HashSet::new();
-        loop {
-            let flashes: Vec<_> = self
-                .levels
-                .iter()
-                .filter(|(node, level)| **level > 9 && !already_flashed.contains(*node))
-                .map(|(n, _)| *n)
-                .collect();
+        neighbours.into_iter().take(count)
+    }
 
-            if flashes.is_empty() {
-                break;
+    fn step(&mut self) -> usize {
+        // Energy level of each octopus increases by one, and any octopus pushed
+        // over 9 is going to flash.
+        let mut worklist = Vec::new();
+        for (index, level) in self.levels.iter_mut().enumerate() {
+            *level += 1;
+            if *level > 9 {
+                worklist.push(index);
             }
+        }
 
-            for node in flashes {
-                already_flashed.insert(node);
-                for dx in -1..=1 {
-                    for dy in -1..=1 {
-                        // this also increases the level of the flashing octopus
-                        // but we're going to set it to zero later
-                        if let Some(level) = self.levels.get_mut(&(node.0 + dx, node.1 + dy)) {
-                            *level += 1;
-                        }
-                    }
+        // Flashing.  Levels only increase until they're reset below, so each
+        // octopus passes 9 (and is added to the worklist) at most once.
+        while let Some(index) = worklist.pop() {
+            for neighbour in self.neighbours(index) {
+                let level = &mut self.levels[neighbour];
+                *level += 1;
+                if *level == 10 {
+                    worklist.push(neighbour);
                 }
             }
         }
 
         // Reset any octopus that flashed
-        for node in &already_flashed {
-            *self.levels.get_mut(node).unwrap() = 0;
+        let mut flashes = 0;
+        for level in &mut self.levels {
+            if *level > 9 {
+                *level = 0;
+                flashes += 1;
+            }
         }
 
-        already_flashed.len()
+        flashes
+    }
+}
+
+impl fmt::Display for Grid {
+    /// Renders the energy level of each octopus, one row per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (y, row) in self.levels.chunks(self.width).enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for level in row {
+                write!(f, "{}", level)?;
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl FromProblemInput<'_> for Grid {
     fn from(lines: &ProblemInput) -> Self {
-        let mut levels = HashMap::new();
+        let width = lines.lines[0].len();
+        let levels = lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .map(|c| c.to_digit(10).unwrap() as u8)
+            .collect();
+
+        Grid::new(width, lines.len(), levels)
+    }
+}
+
+/// The grid settled into a repeating cycle of states without ever having every
+/// octopus flash at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeverSynchronises {
+    /// The first step of the cycle.
+    pub cycle_start: usize,
+    /// The number of steps before the state repeats.
+    pub period: usize,
+}
+
+impl fmt::Display for NeverSynchronises {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "never synchronises: steps repeat every {} from step {}",
+            self.period, self.cycle_start
+        )
+    }
+}
+
+/// Steps a grid forward, recording the flash count of every step so that it can
+/// be looked up later.  Once a state repeats, later steps are answered from the
+/// recorded cycle.
+///
+/// Only the first and latest grids are kept by default, and earlier states are
+/// simulated again from the start when they're asked for.  Use
+/// [`with_stored_states`](Self::with_stored_states) to keep them all instead.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    initial: Grid,
+    /// The grid after every step simulated so far.
+    latest: Grid,
+    /// `states[i]` is the grid after `i` steps, if every state is being kept.
+    states: Option<Vec<Grid>>,
+    /// `flashes[i]` is the number of flashes during step `i + 1`.
+    flashes: Vec<usize>,
+    /// The steps reaching each state, keyed by a hash of the state.  Different
+    /// states can share a hash, so there may be more than one.
+    seen: HashMap<u64, Vec<usize>>,
+    cycle: Option<NeverSynchronises>,
+}
+
+impl Simulation {
+    #[must_use]
+    pub fn new(grid: Grid) -> Self {
+        Self {
+            seen: HashMap::from([(grid.key(), vec![0])]),
+            latest: grid.clone(),
+            initial: grid,
+            states: None,
+            flashes: Vec::new(),
+            cycle: None,
+        }
+    }
+
+    /// Sets whether to keep every state, which makes looking up earlier steps
+    /// cheap at the cost of a copy of the grid per step.
+    #[must_use]
+    pub fn with_stored_states(mut self, stored: bool) -> Self {
+        self.states = stored.then(|| vec![self.initial.clone()]);
+        self
+    }
+
+    /// The number of steps simulated so far.
+    fn simulated(&self) -> usize {
+        self.flashes.len()
+    }
+
+    /// The grid after `step` steps, which must already have been simulated.
+    fn grid_at(&self, step: usize) -> Grid {
+        if let Some(states) = &self.states {
+            return states[step].clone();
+        }
+        if step == self.simulated() {
+            return self.latest.clone();
+        }
+
+        let mut grid = self.initial.clone();
+        for _ in 0..step {
+            grid.step();
+        }
+        grid
+    }
+
+    /// Runs one more step, returning `false` if the states have started repeating
+    /// so there's nothing new to record.
+    fn advance(&mut self) -> bool {
+        if self.cycle.is_some() {
+            return false;
+        }
+
+        self.flashes.push(self.latest.step());
+        let step = self.simulated();
+        if let Some(states) = &mut self.states {
+            states.push(self.latest.clone());
+        }
+
+        let key = self.latest.key();
+        let earlier = self.seen.get(&key).and_then(|steps| {
+            steps
+                .iter()
+                .copied()
+                .find(|&earlier| self.grid_at(earlier).levels == self.latest.levels)
+        });
+
+        match earlier {
+            Some(cycle_start) => {
+                self.cycle = Some(NeverSynchronises {
+                    cycle_start,
+                    period: step - cycle_start,
+                });
+            }
+            None => self.seen.entry(key).or_default().push(step),
+        }
+
+        true
+    }
 
-        for (y, line) in lines.iter().enumerate() {
-            for (x, c) in line.chars().enumerate() {
-                let value = c.to_digit(10).unwrap() as i64;
-                levels.insert((y as i64, x as i64), value);
+    /// Maps `step` onto a step that has already been simulated, simulating
+    /// further if needed.
+    fn recorded(&mut self, step: usize) -> usize {
+        while step > self.simulated() && self.advance() {}
+
+        match self.cycle {
+            Some(cycle) if step >= cycle.cycle_start => {
+                cycle.cycle_start + (step - cycle.cycle_start) % cycle.period
             }
+            _ => step,
         }
+    }
+
+    /// The grid after `step` steps.
+    pub fn state(&mut self, step: usize) -> Grid {
+        let step = self.recorded(step);
+        self.grid_at(step)
+    }
+
+    /// Renders the energy map after `step` steps.
+    pub fn render(&mut self, step: usize) -> String {
+        self.state(step).to_string()
+    }
+
+    /// The number of flashes during step `step`, counting from one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is zero.
+    pub fn flashes(&mut self, step: usize) -> usize {
+        assert!(step > 0, "steps are counted from one");
+
+        // The flashes during a step are determined by the state before it.
+        let before = self.recorded(step - 1);
+        self.recorded(before + 1);
+        self.flashes[before]
+    }
+
+    /// The flash counts of every step simulated so far.
+    #[must_use]
+    pub fn history(&self) -> &[usize] {
+        &self.flashes
+    }
 
-        Grid { levels }
+    /// Finds the first step during which every octopus flashes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the cycle if the grid starts repeating before
+    /// it ever synchronises.
+    pub fn first_synchronised(&mut self) -> Result<usize, NeverSynchronises> {
+        self.first_synchronised_with(|_, _| {})
     }
+
+    /// Finds the first step during which every octopus flashes, calling
+    /// `observe` with each step that has to be simulated along the way and the
+    /// grid after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the cycle if the grid starts repeating before
+    /// it ever synchronises.
+    pub fn first_synchronised_with<F: FnMut(usize, &Grid)>(
+        &mut self,
+        mut observe: F,
+    ) -> Result<usize, NeverSynchronises> {
+        let size = self.initial.size();
+        let mut step = 0;
+
+        loop {
+            if let Some(&flashes) = self.flashes.get(step) {
+                if flashes == size {
+                    return Ok(step + 1);
+                }
+                step += 1;
+            } else if self.advance() {
+                observe(self.simulated(), &self.latest);
+            } else {
+                return Err(self.cycle.unwrap());
+            }
+        }
+    }
+}
+
+fn frame(step: usize, grid: &Grid) -> Frame {
+    Frame::Text(format!("after step {}\n\n{}", step, grid))
 }
 
 impl Solution for Q11 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let mut grid: Grid = lines.parse();
-        (0..100).map(|_| grid.step()).sum::<usize>().to_string()
+        self.part1_visualised(lines, &Discard)
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let mut grid: Grid = lines.parse();
-        (1..)
-            .find(|_| grid.step() == grid.size())
+        self.part2_visualised(lines, &Discard)
+    }
+
+    fn part1_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
+        let mut simulation = Simulation::new(lines.parse());
+        sink.show_with(|| frame(0, &simulation.initial));
+
+        let mut flashes = 0;
+        for step in 1..=100 {
+            flashes += simulation.flashes(step);
+            sink.show_with(|| frame(step, &simulation.state(step)));
+        }
+
+        flashes.to_string()
+    }
+
+    fn part2_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
+        let mut simulation = Simulation::new(lines.parse());
+        sink.show_with(|| frame(0, &simulation.initial));
+
+        simulation
+            .first_synchronised_with(|step, grid| sink.show_with(|| frame(step, grid)))
             .unwrap()
             .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visualise::Recorder;
+
+    const EXAMPLE: [&str; 10] = [
+        "5483143223",
+        "2745854711",
+        "5264556173",
+        "6141336146",
+        "6357385478",
+        "4167524645",
+        "2176841721",
+        "6882881134",
+        "4846848554",
+        "5283751526",
+    ];
+
+    fn example() -> Grid {
+        ProblemInput::from(EXAMPLE.to_vec()).parse()
+    }
+
+    #[test]
+    fn example_answers() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q11.part1(&lines), "1656");
+        assert_eq!(Q11.part2(&lines), "195");
+    }
+
+    #[test]
+    fn example_steps() {
+        let mut simulation = Simulation::new(example());
+        assert_eq!(
+            (1..=10).map(|step| simulation.flashes(step)).sum::<usize>(),
+            204
+        );
+        assert_eq!(
+            simulation.render(2),
+            "8807476555\n5089087054\n8597889608\n8485769600\n8700908800\n\
+             6600088989\n6800005943\n0000007456\n9000000876\n8700006848"
+        );
+    }
+
+    #[test]
+    fn frames_follow_every_step() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        let recorder = Recorder::new();
+        assert_eq!(Q11.part2_visualised(&lines, &recorder), "195");
+
+        let frames = recorder.into_frames();
+        assert_eq!(frames.len(), 196);
+        assert_eq!(
+            frames[195].render(),
+            format!("after step 195\n\n{}", ["0000000000"; 10].join("\n"))
+        );
+    }
+
+    #[test]
+    fn never_synchronises() {
+        let mut simulation = Simulation::new(Grid::new(2, 2, vec![3, 2, 0, 0]));
+        assert_eq!(
+            simulation.first_synchronised(),
+            Err(NeverSynchronises {
+                cycle_start: 7,
+                period: 8
+            })
+        );
+
+        // Steps past the end of what was simulated come from the cycle.
+        assert_eq!(simulation.render(7 + 8 * 100), simulation.render(7));
+        assert_eq!(simulation.flashes(8 + 8 * 100), simulation.flashes(8));
+    }
+
+    #[test]
+    fn stored_states_match_replayed_states() {
+        for grid in [example(), Grid::new(2, 2, vec![3, 2, 0, 0])] {
+            let mut replayed = Simulation::new(grid.clone());
+            let mut stored = Simulation::new(grid).with_stored_states(true);
+
+            for step in (0..40).rev() {
+                assert_eq!(replayed.render(step), stored.render(step));
+            }
+            assert_eq!(replayed.history(), stored.history());
+        }
+    }
+
+    /// Steps `grid` once, returning the flashes and the levels afterwards.
+    fn stepped(mut grid: Grid) -> (usize, String) {
+        let flashes = grid.step();
+        (flashes, grid.to_string())
+    }
+
+    #[test]
+    fn tiny_tori_bump_each_neighbour_once() {
+        // A lone octopus is its own neighbour in every direction, but never
+        // bumps itself.
+        let grid = Grid::new(1, 1, vec![9]).with_toroidal(true);
+        assert_eq!(grid.neighbours(0).count(), 0);
+        assert_eq!(stepped(grid), (1, "0".to_string()));
+
+        // On a 2x2 torus every other octopus is reached through several
+        // directions.  Bumping any of them twice would push it to 10.
+        let grid = Grid::new(2, 2, vec![9, 7, 7, 7]).with_toroidal(true);
+        assert!((0..4).all(|i| grid.neighbours(i).count() == 3));
+        assert_eq!(stepped(grid), (1, "09\n99".to_string()));
+
+        // On a 3x3 torus every other octopus is a neighbour exactly once, even
+        // from a corner.
+        let grid = Grid::new(3, 3, vec![9, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stepped(grid.clone()), (1, "021\n221\n111".to_string()));
+        let grid = grid.with_toroidal(true);
+        assert!((0..9).all(|i| grid.neighbours(i).count() == 8));
+        assert_eq!(stepped(grid), (1, "022\n222\n222".to_string()));
+    }
+
+    #[test]
+    fn wrapping_on_a_non_square_torus() {
+        // Above and below the top left octopus are both the bottom row, and to
+        // its left is the far end of the grid.
+        let grid = Grid::new(4, 2, vec![9, 0, 0, 0, 0, 0, 0, 0]).with_toroidal(true);
+        assert_eq!(stepped(grid), (1, "0212\n2212".to_string()));
+
+        // Flashes cascade across the wrapped edge.
+        let grid = Grid::new(4, 2, vec![0, 0, 0, 9, 8, 0, 0, 0]).with_toroidal(true);
+        assert_eq!(stepped(grid), (2, "3220\n0223".to_string()));
+    }
+
+    #[test]
+    fn large_tori_repeat_their_tiles() {
+        // A torus tiled with copies of the example behaves like each copy is its
+        // own torus, so it must flash exactly as often as all of them together.
+        let tile = example().with_toroidal(true);
+        let tiles = 100;
+        let width = tile.width * tiles;
+        let levels = (0..width * width)
+            .map(|i| {
+                let (x, y) = (i % width % tile.width, i / width % tile.height);
+                tile.levels[y * tile.width + x]
+            })
+            .collect();
+        let mut large = Grid::new(width, width, levels).with_toroidal(true);
+        let mut small = tile;
+
+        let mut flashes = 0;
+        for _ in 0..10 {
+            let step = large.step();
+            assert_eq!(step, small.step() * tiles * tiles);
+            flashes += step;
+        }
+        assert_eq!(flashes, 2_080_000);
+    }
+}
@@ -1,10 +1,12 @@
 use crate::{FromProblemInput, ProblemInput, Solution};
+use anyhow::{anyhow, bail, Result};
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::fmt;
 
 pub struct Q12;
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Node<'a> {
     Lower(&'a str),
     Upper(&'a str),
@@ -15,107 +17,448 @@ impl<'a> Node<'a> {
         matches!(self, Self::Lower(_))
     }
 
-    fn is_start(&self) -> bool {
-        self == &Node::Lower("start")
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        match self {
+            Node::Lower(name) | Node::Upper(name) => name,
+        }
     }
+}
 
-    fn is_end(&self) -> bool {
-        self == &Node::Lower("end")
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl<'a> From<&'a str> for Node<'a> {
+    fn from(n: &'a str) -> Self {
+        if n.chars().next().unwrap().is_uppercase() {
+            Node::Upper(n)
+        } else {
+            Node::Lower(n)
+        }
     }
 }
 
+/// A cave system with every cave interned to an integer id.  Small caves get
+/// the lowest ids, so that a set of visited small caves fits in a bitmask.
 #[derive(Clone, Debug)]
-pub struct Path<'a> {
-    current_node: Node<'a>,
-    node_count: HashMap<Node<'a>, usize>,
+pub struct CaveSystem<'a> {
+    caves: Vec<Node<'a>>,
+    adj: Vec<Vec<usize>>,
+    small_caves: usize,
 }
 
-impl<'a> Path<'a> {
-    #[must_use]
-    pub fn new(node: Node<'a>) -> Self {
-        Self {
-            current_node: node,
-            node_count: HashMap::from([(node, 1)]),
+impl<'a> CaveSystem<'a> {
+    fn id(&self, node: Node<'a>) -> Result<usize> {
+        self.caves
+            .iter()
+            .position(|&n| n == node)
+            .ok_or_else(|| anyhow!("cave system has no {:?} cave", node))
+    }
+
+    fn is_small(&self, id: usize) -> bool {
+        id < self.small_caves
+    }
+
+    /// Checks that paths through this cave system can be enumerated, and sets
+    /// up the rules for moving between caves.
+    fn rules(&self, revisits: usize) -> Result<Rules> {
+        let start = self.id(Node::Lower("start"))?;
+        let end = self.id(Node::Lower("end"))?;
+
+        if self.small_caves > 64 {
+            bail!("too many small caves: {}", self.small_caves);
         }
+        for (cave, neighbours) in self.adj.iter().enumerate() {
+            if let Some(&other) = neighbours
+                .iter()
+                .find(|&&n| !self.is_small(cave) && !self.is_small(n))
+            {
+                bail!(
+                    "infinitely many paths: big caves {:?} and {:?} are connected",
+                    self.caves[cave],
+                    self.caves[other]
+                );
+            }
+        }
+
+        Ok(Rules {
+            start,
+            end,
+            revisits,
+        })
     }
 
-    #[must_use]
-    pub fn count(&self, node: Node<'a>) -> usize {
-        self.node_count.get(&node).copied().unwrap_or_default()
+    /// Counts the paths from `start` to `end` which visit small caves at most once,
+    /// except that up to `revisits` extra visits to small caves (other than
+    /// `start`) are allowed in total.
+    ///
+    /// The number of paths from a cave only depends on which small caves have
+    /// been visited and how many revisits have been used, so counts are memoised
+    /// on that state rather than enumerating every path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `start` or `end` is missing, if there are more small
+    /// caves than fit in a bitmask, if two big caves are connected (in which case
+    /// there could be infinitely many paths), or if the count overflows.
+    pub fn count_paths(&self, revisits: usize) -> Result<u64> {
+        let rules = self.rules(revisits)?;
+        let mut counter = PathCounter {
+            caves: self,
+            rules,
+            memo: HashMap::new(),
+        };
+
+        counter
+            .count(rules.start, rules.initial())
+            .ok_or_else(|| anyhow!("too many paths to count"))
     }
 
-    #[must_use]
-    pub fn append(&self, node: Node<'a>) -> Self {
-        let mut path = self.clone();
-        path.current_node = node;
-        *path.node_count.entry(node).or_default() += 1;
-        path
+    /// Returns a lazy iterator over the paths counted by
+    /// [`count_paths`](Self::count_paths), in depth-first order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as `count_paths`, other than
+    /// overflow.
+    pub fn paths(&self, revisits: usize) -> Result<Paths<'_, 'a>> {
+        let rules = self.rules(revisits)?;
+
+        Ok(Paths {
+            caves: self,
+            rules,
+            max_len: None,
+            must_visit: Vec::new(),
+            stack: vec![(rules.start, rules.initial(), 0)],
+        })
     }
 }
 
-impl<'a> From<&'a str> for Node<'a> {
-    fn from(n: &'a str) -> Self {
-        if n.chars().next().unwrap().is_uppercase() {
-            Node::Upper(n)
+/// The small caves a path has visited so far, and how many extra visits to small
+/// caves it has made.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Visits {
+    visited: u64,
+    used: usize,
+}
+
+/// The rules deciding which caves a path may move into.
+#[derive(Copy, Clone, Debug)]
+struct Rules {
+    start: usize,
+    end: usize,
+    revisits: usize,
+}
+
+impl Rules {
+    fn initial(&self) -> Visits {
+        Visits {
+            visited: 1 << self.start,
+            used: 0,
+        }
+    }
+
+    /// Returns the visits after moving into `next`, or `None` if that move isn't
+    /// allowed.  Paths never return to `start`, can always enter big caves, and
+    /// can only enter a small cave again while they have revisits left.
+    fn enter(&self, caves: &CaveSystem, visits: Visits, next: usize) -> Option<Visits> {
+        let Visits { visited, used } = visits;
+
+        if next == self.start {
+            None
+        } else if !caves.is_small(next) {
+            Some(visits)
+        } else if visited & (1 << next) == 0 {
+            Some(Visits {
+                visited: visited | 1 << next,
+                used,
+            })
+        } else if used < self.revisits {
+            Some(Visits {
+                visited,
+                used: used + 1,
+            })
         } else {
-            Node::Lower(n)
+            None
         }
     }
 }
 
-pub struct Adj<'a>(HashMap<Node<'a>, Vec<Node<'a>>>);
+/// Memoised state for [`CaveSystem::count_paths`].
+struct PathCounter<'c, 'a> {
+    caves: &'c CaveSystem<'a>,
+    rules: Rules,
+    memo: HashMap<(usize, Visits), u64>,
+}
 
-impl<'a> FromProblemInput<'a> for Adj<'a> {
-    fn from(lines: &'a ProblemInput) -> Self {
-        let mut adj = HashMap::new();
+impl PathCounter<'_, '_> {
+    /// Counts the paths to `end` from `cave`, or `None` if the count overflows.
+    fn count(&mut self, cave: usize, visits: Visits) -> Option<u64> {
+        if cave == self.rules.end {
+            return Some(1);
+        }
+        if let Some(&count) = self.memo.get(&(cave, visits)) {
+            return Some(count);
+        }
 
-        for line in lines.iter() {
-            let (l, r) = line.split('-').map(Node::from).collect_tuple().unwrap();
-            adj.entry(l).or_insert_with(Vec::new).push(r);
-            adj.entry(r).or_insert_with(Vec::new).push(l);
+        let caves = self.caves;
+        let mut count = 0;
+        for &next in &caves.adj[cave] {
+            if let Some(next_visits) = self.rules.enter(caves, visits, next) {
+                count = u64::checked_add(count, self.count(next, next_visits)?)?;
+            }
         }
 
-        Adj(adj)
+        self.memo.insert((cave, visits), count);
+        Some(count)
     }
 }
 
-fn count_paths<F: FnMut(&Path, Node) -> bool>(adj: &Adj, mut f: F) -> usize {
-    let mut paths = vec![Path::new(Node::from("start"))];
-    let mut count = 0;
+/// A path through a cave system, displayed in the form `start,A,b,end`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Path<'a>(pub Vec<Node<'a>>);
 
-    while let Some(path) = paths.pop() {
-        if path.current_node.is_end() {
-            count += 1;
-            continue;
-        }
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.iter().join(","))
+    }
+}
 
-        for &n in &adj.0[&path.current_node] {
-            if n.is_start() || f(&path, n) {
+/// A lazy depth-first iterator over the paths through a cave system.
+pub struct Paths<'c, 'a> {
+    caves: &'c CaveSystem<'a>,
+    rules: Rules,
+    max_len: Option<usize>,
+    must_visit: Vec<Node<'a>>,
+    /// The caves on the current path, with the visits made on reaching each one
+    /// and the index of the next neighbour to try from it.
+    stack: Vec<(usize, Visits, usize)>,
+}
+
+impl<'a> Paths<'_, 'a> {
+    /// Only yields paths passing through at most `max_len` caves, including
+    /// `start` and `end`.  Longer paths are never explored.
+    #[must_use]
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Only yields paths which pass through `node`.
+    #[must_use]
+    pub fn must_visit(mut self, node: Node<'a>) -> Self {
+        self.must_visit.push(node);
+        self
+    }
+}
+
+impl<'a> Iterator for Paths<'_, 'a> {
+    type Item = Path<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (cave, visits, next_index) = self.stack.last_mut()?;
+            let next = match self.caves.adj[*cave].get(*next_index) {
+                Some(&next) => next,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            *next_index += 1;
+
+            let next_visits = match self.rules.enter(self.caves, *visits, next) {
+                Some(next_visits) => next_visits,
+                None => continue,
+            };
+            if matches!(self.max_len, Some(max_len) if self.stack.len() >= max_len) {
                 continue;
             }
-            paths.push(path.append(n));
+
+            if next == self.rules.end {
+                let path = Path(
+                    self.stack
+                        .iter()
+                        .map(|&(cave, _, _)| self.caves.caves[cave])
+                        .chain([self.caves.caves[next]])
+                        .collect(),
+                );
+                if self.must_visit.iter().all(|node| path.0.contains(node)) {
+                    return Some(path);
+                }
+            } else {
+                self.stack.push((next, next_visits, 0));
+            }
         }
     }
+}
 
-    count
+impl<'a> FromProblemInput<'a> for CaveSystem<'a> {
+    fn from(lines: &'a ProblemInput) -> Self {
+        let edges: Vec<(Node, Node)> = lines
+            .iter()
+            .map(|line| line.split('-').map(Node::from).collect_tuple().unwrap())
+            .collect();
+
+        // Number the small caves first.
+        let caves: Vec<Node> = edges
+            .iter()
+            .flat_map(|&(l, r)| [l, r])
+            .sorted_by_key(|n| (!n.is_lower(), *n))
+            .dedup()
+            .collect();
+        let small_caves = caves.iter().filter(|n| n.is_lower()).count();
+        let ids: HashMap<Node, usize> = caves.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+
+        let mut adj = vec![Vec::new(); caves.len()];
+        for (l, r) in edges {
+            adj[ids[&l]].push(ids[&r]);
+            adj[ids[&r]].push(ids[&l]);
+        }
+
+        CaveSystem {
+            caves,
+            adj,
+            small_caves,
+        }
+    }
 }
 
 impl Solution for Q12 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let adj: Adj = lines.parse();
-        count_paths(&adj, |path, node| node.is_lower() && path.count(node) > 0).to_string()
+        let caves: CaveSystem = lines.parse();
+        caves.count_paths(0).unwrap().to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let adj: Adj = lines.parse();
-        count_paths(&adj, |path, node| {
-            node.is_lower()
-                && path.count(node) >= 1
-                && path
-                    .node_count
-                    .iter()
-                    .any(|(&n, &v)| n.is_lower() && v >= 2)
-        })
-        .to_string()
+        let caves: CaveSystem = lines.parse();
+        caves.count_paths(1).unwrap().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const SMALL: [&str; 7] = ["start-A", "start-b", "A-c", "A-b", "b-d", "A-end", "b-end"];
+
+    const MEDIUM: [&str; 10] = [
+        "dc-end", "HN-start", "start-kj", "dc-start", "dc-HN", "LN-dc", "HN-end", "kj-sy", "kj-HN",
+        "kj-dc",
+    ];
+
+    const LARGE: [&str; 18] = [
+        "fs-end", "he-DX", "fs-he", "start-DX", "pj-DX", "end-zg", "zg-sl", "zg-pj", "pj-he",
+        "RW-he", "fs-DX", "pj-RW", "zg-RW", "start-pj", "he-WI", "zg-he", "pj-fs", "start-RW",
+    ];
+
+    #[test]
+    fn examples() {
+        for (example, part1, part2) in [
+            (&SMALL[..], "10", "36"),
+            (&MEDIUM[..], "19", "103"),
+            (&LARGE[..], "226", "3509"),
+        ] {
+            let lines = ProblemInput::from(example.to_vec());
+            assert_eq!(Q12.part1(&lines), part1);
+            assert_eq!(Q12.part2(&lines), part2);
+        }
+    }
+
+    #[test]
+    fn more_revisits_allow_more_paths() {
+        let lines = ProblemInput::from(SMALL.to_vec());
+        let caves: CaveSystem = lines.parse();
+
+        let counts: Vec<u64> = (0..4).map(|r| caves.count_paths(r).unwrap()).collect();
+        assert!(counts.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn example_paths() {
+        let lines = ProblemInput::from(SMALL.to_vec());
+        let caves: CaveSystem = lines.parse();
+
+        let mut paths: Vec<String> = caves.paths(0).unwrap().map(|p| p.to_string()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "start,A,b,A,c,A,end",
+                "start,A,b,A,end",
+                "start,A,b,end",
+                "start,A,c,A,b,A,end",
+                "start,A,c,A,b,end",
+                "start,A,c,A,end",
+                "start,A,end",
+                "start,b,A,c,A,end",
+                "start,b,A,end",
+                "start,b,end",
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_agree_with_counts() {
+        for example in [&SMALL[..], &MEDIUM[..], &LARGE[..]] {
+            let lines = ProblemInput::from(example.to_vec());
+            let caves: CaveSystem = lines.parse();
+
+            for revisits in 0..2 {
+                let paths: Vec<Path> = caves.paths(revisits).unwrap().collect();
+                assert_eq!(paths.len() as u64, caves.count_paths(revisits).unwrap());
+
+                let distinct: HashSet<&Path> = paths.iter().collect();
+                assert_eq!(distinct.len(), paths.len());
+            }
+        }
+    }
+
+    #[test]
+    fn paths_can_be_filtered() {
+        let lines = ProblemInput::from(SMALL.to_vec());
+        let caves: CaveSystem = lines.parse();
+
+        let short: Vec<String> = caves
+            .paths(0)
+            .unwrap()
+            .max_len(4)
+            .map(|p| p.to_string())
+            .sorted()
+            .collect();
+        assert_eq!(
+            short,
+            [
+                "start,A,b,end",
+                "start,A,end",
+                "start,b,A,end",
+                "start,b,end"
+            ]
+        );
+
+        let through_c = caves.paths(0).unwrap().must_visit(Node::from("c"));
+        assert_eq!(through_c.count(), 5);
+
+        let none = caves
+            .paths(0)
+            .unwrap()
+            .must_visit(Node::from("c"))
+            .must_visit(Node::from("d"));
+        assert_eq!(none.count(), 0);
+    }
+
+    #[test]
+    fn unsuitable_cave_systems_are_rejected() {
+        let lines = ProblemInput::from(vec!["start-A", "A-b"]);
+        let caves: CaveSystem = lines.parse();
+        assert!(caves.count_paths(0).is_err());
+
+        let lines = ProblemInput::from(vec!["start-A", "A-B", "B-end"]);
+        let caves: CaveSystem = lines.parse();
+        assert!(caves.count_paths(0).is_err());
     }
 }
@@ -1,26 +1,19 @@
-use crate::{FromProblemInput, ProblemInput, Solution};
+use crate::grid::{Axis, PointSet, Position};
+use crate::visualise::{Discard, Frame, Sink};
+use crate::{ocr, FromProblemInput, ProblemInput, Solution};
+use anyhow::Result;
 use itertools::Itertools;
-use std::collections::HashSet;
 use std::str::FromStr;
 
 pub struct Q13;
 
 #[derive(Copy, Clone, Debug)]
-enum Fold {
-    Y(usize),
-    X(usize),
-}
+struct Fold(Axis);
 
 impl Fold {
-    fn apply(self, points: &mut HashSet<(usize, usize)>) {
-        let folder = |(x, y)| -> (usize, usize) {
-            match (self, x, y) {
-                (Fold::Y(f), x, y) if y >= f => (x, f - (y - f)),
-                (Fold::X(f), x, y) if x >= f => (f - (x - f), y),
-                _ => (x, y),
-            }
-        };
-        *points = points.iter().copied().map(folder).collect();
+    fn apply(self, points: &mut PointSet) -> Result<()> {
+        *points = points.fold(self.0)?;
+        Ok(())
     }
 }
 
@@ -30,12 +23,12 @@ impl FromStr for Fold {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.contains('=') {
             let (_, n) = s.split('=').collect_tuple().unwrap();
-            let n: usize = n.parse().unwrap();
+            let n: i64 = n.parse().unwrap();
 
             if s.contains("y=") {
-                Ok(Fold::Y(n))
+                Ok(Fold(Axis::Y(n)))
             } else {
-                Ok(Fold::X(n))
+                Ok(Fold(Axis::X(n)))
             }
         } else {
             Err(())
@@ -43,11 +36,11 @@ impl FromStr for Fold {
     }
 }
 
-struct Origami(HashSet<(usize, usize)>, Vec<Fold>);
+struct Origami(PointSet, Vec<Fold>);
 
 impl FromProblemInput<'_> for Origami {
     fn from(lines: &ProblemInput) -> Self {
-        let mut points = HashSet::new();
+        let mut points = PointSet::new();
         let mut folds = Vec::new();
 
         for line in lines.iter() {
@@ -59,10 +52,10 @@ impl FromProblemInput<'_> for Origami {
             } else {
                 let (l, r) = line
                     .split(',')
-                    .map(|x| x.parse::<usize>().unwrap())
+                    .map(|x| x.parse::<i64>().unwrap())
                     .collect_tuple()
                     .unwrap();
-                points.insert((l, r));
+                points.insert(Position::new(l, r));
             }
         }
 
@@ -72,34 +65,72 @@ impl FromProblemInput<'_> for Origami {
 
 impl Solution for Q13 {
     fn part1(&self, lines: &ProblemInput) -> String {
+        self.part1_visualised(lines, &Discard)
+    }
+
+    fn part2(&self, lines: &ProblemInput) -> String {
+        self.part2_visualised(lines, &Discard)
+    }
+
+    fn part1_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
         let Origami(mut points, folds) = lines.parse();
-        folds[0].apply(&mut points);
+        sink.show_with(|| Frame::Points(points.clone()));
+        folds[0].apply(&mut points).unwrap();
+        sink.show_with(|| Frame::Points(points.clone()));
 
         points.len().to_string()
     }
 
-    fn part2(&self, lines: &ProblemInput) -> String {
+    fn part2_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
         let Origami(mut points, folds) = lines.parse();
+        sink.show_with(|| Frame::Points(points.clone()));
         for fold in folds {
-            fold.apply(&mut points);
+            fold.apply(&mut points).unwrap();
+            sink.show_with(|| Frame::Points(points.clone()));
         }
 
-        let x_max = points.iter().map(|(x, _)| *x).max().unwrap();
-        let y_max = points.iter().map(|(_, y)| *y).max().unwrap();
-
-        let mut output = vec![String::new()];
-        for y in 0..=y_max {
-            let mut line = String::with_capacity(x_max + 1);
-            for x in 0..=x_max {
-                if points.contains(&(x, y)) {
-                    line.push('#');
-                } else {
-                    line.push(' ');
-                }
-            }
-            output.push(line);
+        // Read the letters if possible, but fall back to drawing them.
+        match ocr::read_points(points.iter().map(|p| (p.x, p.y))) {
+            Ok(reading) if reading.is_complete() => reading.text,
+            _ => format!("\n{}", points.render('#', ' ')),
         }
+    }
+}
 
-        output.into_iter().join("\n")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 21] = [
+        "6,10",
+        "0,14",
+        "9,10",
+        "0,3",
+        "10,4",
+        "4,11",
+        "6,0",
+        "6,12",
+        "4,1",
+        "0,13",
+        "10,12",
+        "3,4",
+        "3,0",
+        "8,4",
+        "1,10",
+        "2,14",
+        "8,10",
+        "9,0",
+        "",
+        "fold along y=7",
+        "fold along x=5",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q13.part1(&lines), "17");
+
+        // The example folds into a square, which isn't a letter.
+        assert_eq!(Q13.part2(&lines), "\n#####\n#   #\n#   #\n#   #\n#####");
     }
 }
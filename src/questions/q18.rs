@@ -1,11 +1,14 @@
 use crate::{FromProblemInputLine, ProblemInput, Solution};
-use itertools::Itertools;
+use anyhow::{bail, Result};
 use num::Integer;
+use rayon::prelude::*;
+use std::fmt;
 use std::ops::Add;
+use std::str::FromStr;
 
 pub struct Q18;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Token {
     LBracket,
     RBracket,
@@ -23,17 +26,134 @@ impl Token {
     }
 }
 
-impl From<char> for Token {
-    fn from(value: char) -> Self {
-        match value {
-            '[' => Token::LBracket,
-            ']' => Token::RBracket,
-            ',' => Token::Comma,
-            x => Token::Value(x.to_digit(10).unwrap() as i64),
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::Comma => write!(f, ","),
+            Token::Value(v) => write!(f, "{}", v),
         }
     }
 }
 
+/// Splits a snailfish number into tokens, reading each run of digits as a single
+/// value and skipping whitespace.
+///
+/// # Errors
+///
+/// Returns an error on any other character, or if a value doesn't fit in an
+/// `i64`.
+pub fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '[' => tokens.push(Token::LBracket),
+            ']' => tokens.push(Token::RBracket),
+            ',' => tokens.push(Token::Comma),
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_digit() => {
+                let mut end = start + 1;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    end = i + 1;
+                    chars.next();
+                }
+                tokens.push(Token::Value(s[start..end].parse()?));
+            }
+            c => bail!("unexpected character {:?} at column {}", c, start + 1),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A snailfish number as a recursive tree of pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tree {
+    Value(i64),
+    Pair(Box<Tree>, Box<Tree>),
+}
+
+impl Tree {
+    /// Parses a tree from a complete list of tokens.
+    fn from_tokens(tokens: &[Token]) -> Result<Self> {
+        let mut tokens = tokens.iter().copied();
+        let tree = Self::parse(&mut tokens)?;
+
+        if let Some(token) = tokens.next() {
+            bail!("unexpected {} after the end of the number", token);
+        }
+        Ok(tree)
+    }
+
+    /// Parses a single value or pair from the front of `tokens`.
+    fn parse(tokens: &mut impl Iterator<Item = Token>) -> Result<Self> {
+        match tokens.next() {
+            Some(Token::Value(v)) => Ok(Tree::Value(v)),
+            Some(Token::LBracket) => {
+                let left = Self::parse(tokens)?;
+                expect(tokens, Token::Comma)?;
+                let right = Self::parse(tokens)?;
+                expect(tokens, Token::RBracket)?;
+
+                Ok(Tree::Pair(Box::new(left), Box::new(right)))
+            }
+            Some(token) => bail!("expected a value or pair but found {}", token),
+            None => bail!("expected a value or pair but the number ended"),
+        }
+    }
+
+    fn write_tokens(&self, tokens: &mut Vec<Token>) {
+        match self {
+            Tree::Value(v) => tokens.push(Token::Value(*v)),
+            Tree::Pair(left, right) => {
+                tokens.push(Token::LBracket);
+                left.write_tokens(tokens);
+                tokens.push(Token::Comma);
+                right.write_tokens(tokens);
+                tokens.push(Token::RBracket);
+            }
+        }
+    }
+}
+
+fn expect(tokens: &mut impl Iterator<Item = Token>, expected: Token) -> Result<()> {
+    match tokens.next() {
+        Some(token) if token == expected => Ok(()),
+        Some(token) => bail!("expected {} but found {}", expected, token),
+        None => bail!("expected {} but the number ended", expected),
+    }
+}
+
+impl FromStr for Tree {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_tokens(&tokenize(s)?)
+    }
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tree::Value(v) => write!(f, "{}", v),
+            Tree::Pair(left, right) => write!(f, "[{},{}]", left, right),
+        }
+    }
+}
+
+impl From<&LinearTree> for Tree {
+    fn from(tree: &LinearTree) -> Self {
+        // The tokens of a `LinearTree` always form a well-formed number.
+        Tree::from_tokens(&tree.tokens).unwrap()
+    }
+}
+
 struct LinearTreeIter<'a> {
     tokens: &'a [Token],
     index: usize,
@@ -87,7 +207,225 @@ impl<'a> Iterator for LinearTreeIter<'a> {
     }
 }
 
-#[derive(Debug, Clone)]
+/// The thresholds at which a snailfish number needs reducing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    /// A pair of values nested inside at least this many other pairs explodes.
+    explode_depth: usize,
+    /// Values at least this large split.
+    split_at: i64,
+}
+
+impl Rules {
+    /// Creates rules where a pair of values nested inside at least
+    /// `explode_depth` other pairs explodes, and values of at least `split_at`
+    /// split.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `split_at` is less than 2, as values must split into
+    /// two smaller values for reduction to finish.
+    pub fn new(explode_depth: usize, split_at: i64) -> Result<Self> {
+        if split_at < 2 {
+            bail!("values must split at 2 or more, not {}", split_at);
+        }
+
+        Ok(Self {
+            explode_depth,
+            split_at,
+        })
+    }
+
+    #[must_use]
+    pub fn explode_depth(self) -> usize {
+        self.explode_depth
+    }
+
+    #[must_use]
+    pub fn split_at(self) -> i64 {
+        self.split_at
+    }
+}
+
+impl Default for Rules {
+    /// The rules from the puzzle: pairs inside four pairs explode, and values of
+    /// ten or more split.
+    fn default() -> Self {
+        Self {
+            explode_depth: 4,
+            split_at: 10,
+        }
+    }
+}
+
+/// Which side of a pair to descend into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// The route from the root of a snailfish number to one of its elements,
+/// displayed as a string of `L` and `R`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path(pub Vec<Side>);
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for side in &self.0 {
+            match side {
+                Side::Left => write!(f, "L")?,
+                Side::Right => write!(f, "R")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ActionKind {
+    Explode,
+    Split,
+}
+
+/// A single step in reducing a snailfish number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The pair of values at `path` exploded.
+    Explode { path: Path, left: i64, right: i64 },
+    /// The value at `path` split into a pair.
+    Split { path: Path, value: i64 },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Explode { path, left, right } => {
+                write!(f, "explode [{},{}] at {}", left, right, path)
+            }
+            Action::Split { path, value } => write!(f, "split {} at {}", value, path),
+        }
+    }
+}
+
+/// An action taken while reducing a number, and the number that resulted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub action: Action,
+    pub result: LinearTree,
+}
+
+/// An iterator over the steps of reducing a snailfish number.
+#[derive(Clone, Debug)]
+pub struct Reduction {
+    tree: LinearTree,
+    rules: Rules,
+}
+
+impl Iterator for Reduction {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, kind) = self.tree.next_action(self.rules)?;
+        let value = |index: usize| self.tree.tokens[index].as_value().unwrap();
+
+        let action = match kind {
+            ActionKind::Explode => {
+                let mut path = self.tree.path_to(index);
+                path.0.pop();
+                let (left, right) = (value(index), value(index + 2));
+
+                self.tree.explode(index);
+                Action::Explode { path, left, right }
+            }
+            ActionKind::Split => {
+                let path = self.tree.path_to(index);
+                let value = value(index);
+
+                self.tree.split(index);
+                Action::Split { path, value }
+            }
+        };
+
+        Some(Step {
+            action,
+            result: self.tree.clone(),
+        })
+    }
+}
+
+/// A position within a list of tokens, which can be moved and edited around in
+/// constant time.
+struct Cursor {
+    /// The tokens before the cursor, in order.
+    before: Vec<Token>,
+    /// The tokens after the cursor, in reverse order.
+    after: Vec<Token>,
+    /// The number of pairs that are open at the cursor.
+    depth: usize,
+}
+
+impl Cursor {
+    /// Creates a cursor at the start of `tokens`.
+    fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.reverse();
+        Self {
+            before: Vec::with_capacity(tokens.len()),
+            after: tokens,
+            depth: 0,
+        }
+    }
+
+    /// Moves past the next token, returning it.
+    fn forward(&mut self) -> Option<Token> {
+        let token = self.after.pop()?;
+        match token {
+            Token::LBracket => self.depth += 1,
+            Token::RBracket => self.depth -= 1,
+            _ => {}
+        }
+        self.before.push(token);
+
+        Some(token)
+    }
+
+    /// Moves back before the previous token, returning it.
+    fn back(&mut self) -> Option<Token> {
+        let token = self.before.pop()?;
+        match token {
+            Token::LBracket => self.depth -= 1,
+            Token::RBracket => self.depth += 1,
+            _ => {}
+        }
+        self.after.push(token);
+
+        Some(token)
+    }
+
+    /// Adds `v` to the nearest value before the cursor, returning its index in
+    /// `before` if there is one.
+    fn add_to_previous(&mut self, v: i64) -> Option<usize> {
+        let index = self.before.iter().rposition(|t| t.as_value().is_some())?;
+        if let Token::Value(value) = &mut self.before[index] {
+            *value += v;
+        }
+
+        Some(index)
+    }
+
+    /// Adds `v` to the nearest value after the cursor.
+    fn add_to_next(&mut self, v: i64) {
+        if let Some(Token::Value(value)) =
+            self.after.iter_mut().rev().find(|t| t.as_value().is_some())
+        {
+            *value += v;
+        }
+    }
+}
+
+/// A snailfish number as the flat list of tokens it's written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LinearTree {
     tokens: Vec<Token>,
 }
@@ -99,18 +437,131 @@ impl LinearTree {
             .sum()
     }
 
-    fn resolve(&mut self) {
-        'rl: loop {
-            if let Some(v) = self.value_iter().find(|v| v.total_depth() > 4) {
-                self.explode(v.index);
-                continue 'rl;
+    /// Reduces this number with the given rules.
+    ///
+    /// This makes the same reductions as [`trace`](Self::trace), but rather than
+    /// searching from the start for every action, it makes a single pass over
+    /// the tokens with a cursor, which only has to step back after a split when
+    /// the value to its left needs splitting too.
+    pub fn reduce(&mut self, rules: Rules) {
+        let mut cursor = Cursor::new(std::mem::take(&mut self.tokens));
+
+        // Explosions come first.  Exploding a pair only changes values, so a pair
+        // can only need to explode once its last token has been passed, and
+        // after exploding nothing to the left of the cursor will need to.
+        while let Some(token) = cursor.forward() {
+            if token == Token::RBracket && cursor.depth + 1 > rules.explode_depth {
+                if let [.., Token::LBracket, Token::Value(left), Token::Comma, Token::Value(right), Token::RBracket] =
+                    *cursor.before
+                {
+                    cursor.before.truncate(cursor.before.len() - 5);
+                    cursor.add_to_previous(left);
+                    cursor.add_to_next(right);
+                    cursor.before.push(Token::Value(0));
+                }
+            }
+        }
+
+        // Then splits, from the start.  A split can only create one pair which
+        // needs exploding, and which can only push the value to its left (which
+        // is already past the cursor) over the threshold.
+        while cursor.back().is_some() {}
+        while let Some(token) = cursor.after.pop() {
+            let v = match token {
+                Token::Value(v) if v >= rules.split_at => v,
+                token => {
+                    cursor.after.push(token);
+                    cursor.forward();
+                    continue;
+                }
+            };
+            let (left, right) = (v.div_floor(&2), v.div_ceil(&2));
+
+            if cursor.depth + 1 > rules.explode_depth {
+                let previous = cursor.add_to_previous(left);
+                cursor.add_to_next(right);
+                cursor.after.push(Token::Value(0));
+
+                if let Some(index) = previous {
+                    if cursor.before[index].as_value().unwrap() >= rules.split_at {
+                        while cursor.before.len() > index {
+                            cursor.back();
+                        }
+                    }
+                }
+            } else {
+                cursor.after.extend([
+                    Token::RBracket,
+                    Token::Value(right),
+                    Token::Comma,
+                    Token::Value(left),
+                    Token::LBracket,
+                ]);
             }
-            if let Some(v) = self.value_iter().find(|v| v.value >= 10) {
-                self.split(v.index);
-                continue 'rl;
+        }
+
+        self.tokens = cursor.before;
+    }
+
+    /// Reduces this number with the given rules, yielding every action taken
+    /// along with the number it produced.
+    #[must_use]
+    pub fn trace(self, rules: Rules) -> Reduction {
+        Reduction { tree: self, rules }
+    }
+
+    /// Forms the pair `[self,rhs]` without reducing it.
+    #[must_use]
+    pub fn join(&self, rhs: &LinearTree) -> LinearTree {
+        let mut tokens = Vec::with_capacity(self.tokens.len() + rhs.tokens.len() + 3);
+        tokens.push(Token::LBracket);
+        tokens.extend(&self.tokens);
+        tokens.push(Token::Comma);
+        tokens.extend(&rhs.tokens);
+        tokens.push(Token::RBracket);
+
+        LinearTree { tokens }
+    }
+
+    /// Finds the next reduction to make: the leftmost pair of values nested too
+    /// deeply explodes, or failing that the leftmost value that's too large
+    /// splits.  Returns the index of the value involved.
+    fn next_action(&self, rules: Rules) -> Option<(usize, ActionKind)> {
+        let is_pair_of_values = |index: usize| {
+            index > 0
+                && self.tokens[index - 1] == Token::LBracket
+                && self.tokens.get(index + 1) == Some(&Token::Comma)
+                && matches!(self.tokens.get(index + 2), Some(Token::Value(_)))
+        };
+
+        if let Some(v) = self
+            .value_iter()
+            .find(|v| v.total_depth() > rules.explode_depth && is_pair_of_values(v.index))
+        {
+            return Some((v.index, ActionKind::Explode));
+        }
+        if let Some(v) = self.value_iter().find(|v| v.value >= rules.split_at) {
+            return Some((v.index, ActionKind::Split));
+        }
+
+        None
+    }
+
+    /// Returns the path from the root to the token at `index`.
+    fn path_to(&self, index: usize) -> Path {
+        let mut path = Vec::new();
+        for token in &self.tokens[..index] {
+            match token {
+                Token::LBracket => path.push(Side::Left),
+                Token::Comma => *path.last_mut().unwrap() = Side::Right,
+                Token::RBracket => {
+                    path.pop();
+                }
+                Token::Value(_) => {}
             }
-            break;
         }
+
+        Path(path)
     }
 
     fn value_iter(&self) -> LinearTreeIter<'_> {
@@ -156,11 +607,36 @@ impl LinearTree {
     }
 }
 
+impl From<&Tree> for LinearTree {
+    fn from(tree: &Tree) -> Self {
+        let mut tokens = Vec::new();
+        tree.write_tokens(&mut tokens);
+
+        LinearTree { tokens }
+    }
+}
+
+impl FromStr for LinearTree {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(LinearTree::from(&s.parse::<Tree>()?))
+    }
+}
+
+impl fmt::Display for LinearTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}", token)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl FromProblemInputLine for LinearTree {
     fn from_line(line: &str) -> Self {
-        LinearTree {
-            tokens: line.chars().map(Token::from).collect(),
-        }
+        line.parse().unwrap()
     }
 }
 
@@ -168,15 +644,8 @@ impl Add for &LinearTree {
     type Output = LinearTree;
 
     fn add(self, rhs: Self) -> Self::Output {
-        let mut tokens = Vec::with_capacity(self.tokens.len() + rhs.tokens.len() + 3);
-        tokens.push(Token::LBracket);
-        tokens.extend(&self.tokens);
-        tokens.push(Token::Comma);
-        tokens.extend(&rhs.tokens);
-        tokens.push(Token::RBracket);
-
-        let mut tree = LinearTree { tokens };
-        tree.resolve();
+        let mut tree = self.join(rhs);
+        tree.reduce(Rules::default());
         tree
     }
 }
@@ -202,12 +671,171 @@ impl Solution for Q18 {
 
     fn part2(&self, lines: &ProblemInput) -> String {
         let linear_trees: Vec<LinearTree> = lines.parse();
-        linear_trees
-            .iter()
-            .permutations(2)
-            .map(|p| (p[0] + p[1]).magnitude())
+        let n = linear_trees.len();
+        (0..n)
+            .into_par_iter()
+            .flat_map(|i| (0..n).into_par_iter().map(move |j| (i, j)))
+            .filter(|(i, j)| i != j)
+            .map(|(i, j)| (&linear_trees[i] + &linear_trees[j]).magnitude())
             .max()
             .unwrap()
             .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOMEWORK: [&str; 10] = [
+        "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]",
+        "[[[5,[2,8]],4],[5,[[9,9],0]]]",
+        "[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]",
+        "[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]",
+        "[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]",
+        "[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]",
+        "[[[[5,4],[7,7]],8],[[8,3],8]]",
+        "[[9,3],[[9,9],[6,[4,9]]]]",
+        "[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]",
+        "[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(HOMEWORK.to_vec());
+        assert_eq!(Q18.part1(&lines), "4140");
+        assert_eq!(Q18.part2(&lines), "3993");
+
+        let trees: Vec<LinearTree> = lines.parse();
+        assert_eq!(
+            trees.into_iter().reduce(Add::add).unwrap().to_string(),
+            "[[[[6,6],[7,6]],[[7,7],[7,0]]],[[[7,7],[7,7]],[[7,8],[9,9]]]]"
+        );
+    }
+
+    #[test]
+    fn magnitudes() {
+        for (number, magnitude) in [
+            ("[[1,2],[[3,4],5]]", 143),
+            ("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]", 1384),
+            ("[[[[1,1],[2,2]],[3,3]],[4,4]]", 445),
+            (
+                "[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]",
+                3488,
+            ),
+        ] {
+            assert_eq!(number.parse::<LinearTree>().unwrap().magnitude(), magnitude);
+        }
+    }
+
+    #[test]
+    fn tokens_and_trees_round_trip() {
+        assert_eq!(
+            tokenize("[12, [3,456]]").unwrap(),
+            [
+                Token::LBracket,
+                Token::Value(12),
+                Token::Comma,
+                Token::LBracket,
+                Token::Value(3),
+                Token::Comma,
+                Token::Value(456),
+                Token::RBracket,
+                Token::RBracket,
+            ]
+        );
+
+        for number in HOMEWORK.iter().chain(&["7", "[10,[200,3]]"]) {
+            let tree: Tree = number.parse().unwrap();
+            let linear = LinearTree::from(&tree);
+
+            assert_eq!(tree.to_string(), *number);
+            assert_eq!(linear.to_string(), *number);
+            assert_eq!(Tree::from(&linear), tree);
+        }
+
+        assert_eq!(
+            "[1,2]".parse::<Tree>().unwrap(),
+            Tree::Pair(Box::new(Tree::Value(1)), Box::new(Tree::Value(2)))
+        );
+    }
+
+    #[test]
+    fn malformed_numbers_are_rejected() {
+        for number in ["", "[1,2", "[1,2]]", "[1;2]", "[1,2,3]", "[,1]", "[1,x]"] {
+            assert!(number.parse::<Tree>().is_err(), "{:?} parsed", number);
+        }
+        assert!(tokenize("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn rules_reject_values_that_cannot_split() {
+        assert!(Rules::new(4, 1).is_err());
+        assert!(Rules::new(4, 0).is_err());
+        assert!(Rules::new(4, -3).is_err());
+        assert_eq!(Rules::new(4, 10).unwrap(), Rules::default());
+    }
+
+    #[test]
+    fn trace_follows_the_worked_example() {
+        let tree: LinearTree = "[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]".parse().unwrap();
+        let steps: Vec<Step> = tree.trace(Rules::default()).collect();
+
+        let actions: Vec<String> = steps.iter().map(|s| s.action.to_string()).collect();
+        assert_eq!(
+            actions,
+            [
+                "explode [4,3] at LLLL",
+                "explode [8,4] at LRRL",
+                "split 15 at LRL",
+                "split 13 at LRRR",
+                "explode [6,7] at LRRR",
+            ]
+        );
+        assert_eq!(
+            steps.last().unwrap().result.to_string(),
+            "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"
+        );
+    }
+
+    #[test]
+    fn reduce_matches_trace() {
+        let trees: Vec<LinearTree> = HOMEWORK.iter().map(|n| n.parse().unwrap()).collect();
+
+        for explode_depth in 1..=5 {
+            for split_at in [2, 5, 10] {
+                let rules = Rules::new(explode_depth, split_at).unwrap();
+
+                for a in &trees {
+                    for b in &trees {
+                        let joined = a.join(b);
+                        let traced = joined
+                            .clone()
+                            .trace(rules)
+                            .last()
+                            .map_or_else(|| joined.clone(), |step| step.result);
+
+                        let mut reduced = joined;
+                        reduced.reduce(rules);
+                        assert_eq!(reduced, traced, "{} + {} with {:?}", a, b, rules);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reduce_handles_large_values() {
+        let mut tree: LinearTree = "[[[[0,0],0],0],[100,0]]".parse().unwrap();
+        tree.reduce(Rules::default());
+
+        let traced = "[[[[0,0],0],0],[100,0]]"
+            .parse::<LinearTree>()
+            .unwrap()
+            .trace(Rules::default())
+            .last()
+            .unwrap()
+            .result;
+        assert_eq!(tree, traced);
+    }
+}
@@ -1,127 +1,540 @@
+use anyhow::{bail, Result};
 use defaultmap::DefaultHashMap;
 use itertools::Itertools;
-use nalgebra::{Matrix3, Vector3};
-use petgraph::algo::astar;
-use petgraph::Directed;
-use std::collections::HashSet;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
 
-use crate::{FromProblemInput, NamedGraph, ProblemInput, Skip, Solution};
+use crate::space::{Position3, Rotation};
+use crate::{FromProblemInput, ProblemInput, Skip, Solution};
 
 pub struct Q19;
 
+/// The number of beacons two scanners must both see for them to be aligned, in
+/// the puzzle.
+pub const MIN_OVERLAP: usize = 12;
+
+/// A rotation followed by a translation, taking one scanner's coordinates into
+/// another's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pose {
+    pub rotation: Rotation,
+    pub translation: Position3,
+}
+
+impl Pose {
+    #[must_use]
+    pub fn identity() -> Self {
+        Self {
+            rotation: Rotation::identity(),
+            translation: Position3::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn apply(&self, v: Position3) -> Position3 {
+        self.rotation.apply(v) + self.translation
+    }
+
+    /// The pose which undoes this one.
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        Self {
+            rotation,
+            translation: -rotation.apply(self.translation),
+        }
+    }
+
+    /// The pose which applies `other` and then this one.
+    #[must_use]
+    pub fn compose(&self, other: &Pose) -> Self {
+        Self {
+            rotation: self.rotation.compose(other.rotation),
+            translation: self.apply(other.translation),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-struct Scanner {
-    positions: Vec<Vector3<i64>>,
+pub struct Scanner {
+    pub positions: Vec<Position3>,
 }
 
 impl FromProblemInput<'_> for Scanner {
     fn from(lines: &ProblemInput) -> Self {
         let result: Vec<Vec<i64>> = lines.split(1..).parse();
-        let positions = result.into_iter().map(Vector3::from_vec).collect();
+        let positions = result
+            .into_iter()
+            .map(|v| Position3::new(v[0], v[1], v[2]))
+            .collect();
         Scanner { positions }
     }
 }
 
-fn sign_vectors() -> [Vector3<i64>; 6] {
-    [
-        Vector3::from([1, 0, 0]),
-        Vector3::from([0, 1, 0]),
-        Vector3::from([0, 0, 1]),
-        Vector3::from([-1, 0, 0]),
-        Vector3::from([0, -1, 0]),
-        Vector3::from([0, 0, -1]),
-    ]
+/// Finds the pose taking `q`'s coordinates into `p`'s, if they see at least
+/// `min_overlap` beacons in common, by trying every rotation and counting the
+/// translations between every pair of beacons.  This is much slower than
+/// [`align`], but simple enough to check it against.
+///
+/// Scanners are only ever aligned on a beacon they both see, so a `min_overlap`
+/// of zero is treated as one.
+#[must_use]
+pub fn resolve(p: &Scanner, q: &Scanner, min_overlap: usize) -> Option<Pose> {
+    let min_overlap = min_overlap.max(1);
+    for rotation in Rotation::all() {
+        let mut overlaps = DefaultHashMap::new(0);
+        for &q_position in &q.positions {
+            let q_normalized = rotation.apply(q_position);
+            for &p_position in &p.positions {
+                overlaps[p_position - q_normalized] += 1;
+            }
+        }
+        if let Some((&translation, _)) = overlaps.iter().find(|(_, v)| **v >= min_overlap) {
+            return Some(Pose {
+                rotation,
+                translation,
+            });
+        }
+    }
+    None
 }
 
-fn bases() -> Vec<Matrix3<i64>> {
-    sign_vectors()
-        .into_iter()
-        .permutations(2)
-        .filter(|p| p[0].dot(&p[1]) == 0)
-        .map(|p| Matrix3::from_columns(&[p[0], p[1], p[0].cross(&p[1])]).transpose())
-        .collect()
+/// The distances between a scanner's beacons, which don't depend on the way the
+/// scanner is facing.
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    /// The squared distances from each beacon to every other beacon, sorted.
+    beacons: Vec<Vec<i64>>,
+    /// The squared distances between every pair of beacons, sorted.
+    pairs: Vec<i64>,
 }
 
-fn resolve(p: &Scanner, q: &Scanner) -> Option<(Matrix3<i64>, Vector3<i64>)> {
-    for base in bases() {
-        let mut overlaps = DefaultHashMap::new(0);
-        for q_position in &q.positions {
-            let q_normalized = base * q_position;
-            for p_position in &p.positions {
-                let delta = p_position - q_normalized;
-                overlaps[(delta.x, delta.y, delta.z)] += 1;
+impl Fingerprint {
+    #[must_use]
+    pub fn new(scanner: &Scanner) -> Self {
+        let beacons: Vec<Vec<i64>> = scanner
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let mut distances: Vec<i64> = scanner
+                    .positions
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| i != j)
+                    .map(|(_, b)| a.distance_squared(*b))
+                    .collect();
+                distances.sort_unstable();
+                distances
+            })
+            .collect();
+
+        let mut pairs: Vec<i64> = scanner
+            .positions
+            .iter()
+            .tuple_combinations()
+            .map(|(a, b)| a.distance_squared(*b))
+            .collect();
+        pairs.sort_unstable();
+
+        Self { beacons, pairs }
+    }
+}
+
+/// Counts the values two sorted lists have in common, including repeats.
+fn shared(a: &[i64], b: &[i64]) -> usize {
+    let (mut i, mut j, mut count) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                count += 1;
+                i += 1;
+                j += 1;
             }
         }
-        if let Some((&(x, y, z), _)) = overlaps.iter().find(|(_, v)| **v >= 12) {
-            return Some((base, Vector3::new(x, y, z)));
+    }
+    count
+}
+
+/// Finds the pose taking `q`'s coordinates into `p`'s, in the same way as
+/// [`resolve`], including treating a `min_overlap` of zero as one.
+///
+/// If the scanners overlap, the distances between the beacons they share will
+/// appear in both fingerprints, so scanners with too few distances in common are
+/// skipped straight away.  Beacons which could be the same are then paired up by
+/// how many distances to other beacons they share, and only the translations
+/// given by those pairs are tried for each rotation.
+#[must_use]
+pub fn align(
+    (p, p_print): (&Scanner, &Fingerprint),
+    (q, q_print): (&Scanner, &Fingerprint),
+    min_overlap: usize,
+) -> Option<Pose> {
+    let min_overlap = min_overlap.max(1);
+    if shared(&p_print.pairs, &q_print.pairs) < min_overlap * (min_overlap - 1) / 2 {
+        return None;
+    }
+
+    let candidates: Vec<(usize, usize)> = (0..p.positions.len())
+        .cartesian_product(0..q.positions.len())
+        .filter(|&(i, j)| shared(&p_print.beacons[i], &q_print.beacons[j]) >= min_overlap - 1)
+        .collect();
+    if candidates.len() < min_overlap {
+        return None;
+    }
+
+    let p_positions: HashSet<_> = p.positions.iter().collect();
+    for rotation in Rotation::all() {
+        for &(i, j) in &candidates {
+            let delta = p.positions[i] - rotation.apply(q.positions[j]);
+            let overlap = q
+                .positions
+                .iter()
+                .filter(|&&q_position| p_positions.contains(&(rotation.apply(q_position) + delta)))
+                .count();
+
+            if overlap >= min_overlap {
+                return Some(Pose {
+                    rotation,
+                    translation: delta,
+                });
+            }
         }
     }
     None
 }
 
-fn solve_scanners(lines: &ProblemInput) -> (Vec<Vector3<i64>>, Vec<Vector3<i64>>) {
-    let scanners: Vec<Scanner> = lines.parse::<Skip<Scanner>>().unwrap();
-    let mut graph: NamedGraph<Scanner, (Matrix3<i64>, Vector3<i64>), usize, Directed> =
-        NamedGraph::new();
+/// The scanners placed relative to scanner 0, and the beacons they see.
+#[derive(Debug, Clone)]
+pub struct ScannerMap {
+    /// The pose taking each scanner's coordinates into scanner 0's, or `None` if
+    /// it doesn't overlap enough with any scanner that could be placed.
+    pub poses: Vec<Option<Pose>>,
+    /// Every beacon seen by a placed scanner, in scanner 0's coordinates.
+    pub beacons: HashSet<Position3>,
+}
+
+impl ScannerMap {
+    /// Places as many scanners as possible relative to the first, by aligning
+    /// every pair which sees at least `min_overlap` beacons in common.
+    #[must_use]
+    pub fn new(scanners: &[Scanner], min_overlap: usize) -> Self {
+        let prints: Vec<Fingerprint> = scanners.par_iter().map(Fingerprint::new).collect();
 
-    for (i, scanner) in scanners.into_iter().enumerate() {
-        graph.insert(i, scanner);
+        // Aligning `q` to `p` also gives the alignment of `p` to `q`, so only try
+        // each pair once.
+        let n = scanners.len();
+        let alignments: Vec<_> = (0..n)
+            .into_par_iter()
+            .flat_map(|i| ((i + 1)..n).into_par_iter().map(move |j| (i, j)))
+            .filter_map(|(i, j)| {
+                let pose = align(
+                    (&scanners[i], &prints[i]),
+                    (&scanners[j], &prints[j]),
+                    min_overlap,
+                )?;
+                Some((i, j, pose))
+            })
+            .collect();
+
+        let mut neighbours = vec![Vec::new(); n];
+        for (i, j, pose) in alignments {
+            neighbours[i].push((j, pose));
+            neighbours[j].push((i, pose.inverse()));
+        }
+
+        // Work outwards from scanner 0, placing each scanner from the first of
+        // its neighbours to be placed.
+        let mut poses = vec![None; n];
+        let mut queue = VecDeque::new();
+        if n > 0 {
+            poses[0] = Some(Pose::identity());
+            queue.push_back(0);
+        }
+        while let Some(i) = queue.pop_front() {
+            let pose = poses[i].unwrap();
+            for &(j, relative) in &neighbours[i] {
+                if poses[j].is_none() {
+                    poses[j] = Some(pose.compose(&relative));
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        let beacons = scanners
+            .iter()
+            .zip(&poses)
+            .filter_map(|(scanner, pose)| Some((scanner, pose.as_ref()?)))
+            .flat_map(|(scanner, pose)| scanner.positions.iter().map(move |&v| pose.apply(v)))
+            .collect();
+
+        Self { poses, beacons }
     }
 
-    let edges: Vec<_> = graph
-        .nodes_iter()
-        .permutations(2)
-        .filter_map(|v| resolve(v[0].2, v[1].2).map(|w| (v[0].0, v[1].0, w)))
-        .collect();
+    /// The indices of the scanners which couldn't be placed.
+    #[must_use]
+    pub fn unplaced(&self) -> Vec<usize> {
+        (0..self.poses.len())
+            .filter(|&i| self.poses[i].is_none())
+            .collect()
+    }
 
-    for (i, j, weight) in edges {
-        graph.insert_edge(&i, &j, weight);
-    }
-
-    let mut positions = HashSet::new();
-    let mut scanners = Vec::new();
-
-    for (i, _, scanner) in graph.nodes_iter() {
-        let path = astar(
-            &graph.graph,
-            graph.get_index(&0).unwrap(),
-            |n| n == graph.get_index(&i).unwrap(),
-            |_| 1,
-            |_| 0,
-        )
-        .unwrap()
-        .1;
-
-        let (p, b) = path.into_iter().tuple_windows().fold(
-            (Vector3::zeros(), Matrix3::<i64>::identity()),
-            |acc, (l, r)| {
-                let weight = graph
-                    .graph
-                    .edge_weight(graph.graph.find_edge(l, r).unwrap())
-                    .unwrap();
-                (acc.0 + acc.1 * weight.1, acc.1 * weight.0)
-            },
-        );
-        positions.extend(scanner.positions.iter().map(|v| b * v + p));
-        scanners.push(p);
+    /// The positions of the placed scanners, in scanner 0's coordinates.
+    pub fn scanner_positions(&self) -> impl Iterator<Item = Position3> + Clone + '_ {
+        self.poses.iter().flatten().map(|pose| pose.translation)
     }
+}
+
+/// Reads the scanners and places every one of them relative to the first.
+///
+/// # Errors
+///
+/// Returns an error listing the scanners which couldn't be placed.
+fn place_all(lines: &ProblemInput) -> Result<ScannerMap> {
+    let scanners: Vec<Scanner> = lines.parse::<Skip<Scanner>>().unwrap();
+    let map = ScannerMap::new(&scanners, MIN_OVERLAP);
 
-    (positions.into_iter().collect(), scanners)
+    let unplaced = map.unplaced();
+    if !unplaced.is_empty() {
+        bail!(
+            "scanners {:?} don't overlap enough with any placed scanner",
+            unplaced
+        );
+    }
+    Ok(map)
 }
 
 impl Solution for Q19 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        solve_scanners(lines).0.len().to_string()
+        let map = place_all(lines).unwrap();
+        map.beacons.len().to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        solve_scanners(lines)
-            .1
-            .into_iter()
+        let map = place_all(lines).unwrap();
+
+        // With fewer than two scanners, none are any distance apart.
+        map.scanner_positions()
             .tuple_combinations()
-            .map(|(l, r)| (l - r).abs().sum())
+            .map(|(l, r)| l.manhattan(r))
             .max()
-            .unwrap()
+            .unwrap_or(0)
             .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Random;
+
+    /// Pseudo-random beacons spread through a cube.
+    fn cloud(n: usize, seed: u64) -> Vec<Position3> {
+        let mut rng = Random::new(seed);
+        let mut coordinate = move || rng.below(2000) - 1000;
+
+        (0..n)
+            .map(|_| Position3::new(coordinate(), coordinate(), coordinate()))
+            .collect()
+    }
+
+    /// The scanner at `pose` which sees `beacons`, given in world coordinates.
+    fn scanner_at(pose: Pose, beacons: &[Position3]) -> Scanner {
+        let inverse = pose.inverse();
+        Scanner {
+            positions: beacons.iter().map(|&b| inverse.apply(b)).collect(),
+        }
+    }
+
+    fn pose(rotation: usize, x: i64, y: i64, z: i64) -> Pose {
+        Pose {
+            rotation: Rotation::all().nth(rotation).unwrap(),
+            translation: Position3::new(x, y, z),
+        }
+    }
+
+    #[test]
+    fn poses_compose_and_invert() {
+        let (a, b) = (pose(5, 10, -20, 30), pose(17, -4, 0, 9));
+        let v = Position3::new(3, -7, 11);
+
+        assert_eq!(a.compose(&b).apply(v), a.apply(b.apply(v)));
+        assert_eq!(a.inverse().apply(a.apply(v)), v);
+        assert_eq!(a.compose(&a.inverse()), Pose::identity());
+    }
+
+    #[test]
+    fn align_agrees_with_resolve() {
+        let beacons = cloud(40, 1);
+        let p = scanner_at(pose(0, 0, 0, 0), &beacons[..25]);
+
+        for (rotation, q_pose) in Rotation::all().zip(1..) {
+            let q_pose = Pose {
+                rotation,
+                translation: Position3::new(q_pose * 37, -500, q_pose * -11),
+            };
+            let q = scanner_at(q_pose, &beacons[13..]);
+            let (p_print, q_print) = (Fingerprint::new(&p), Fingerprint::new(&q));
+
+            let aligned = align((&p, &p_print), (&q, &q_print), MIN_OVERLAP);
+            assert_eq!(aligned, Some(q_pose));
+            assert_eq!(aligned, resolve(&p, &q, MIN_OVERLAP));
+
+            // The scanners share 12 beacons, so can't be aligned on 13.
+            assert_eq!(align((&p, &p_print), (&q, &q_print), 13), None);
+            assert_eq!(resolve(&p, &q, 13), None);
+        }
+    }
+
+    #[test]
+    fn unrelated_scanners_do_not_align() {
+        let p = scanner_at(Pose::identity(), &cloud(30, 2));
+        let q = scanner_at(pose(7, 100, 200, 300), &cloud(30, 3));
+
+        assert_eq!(
+            align(
+                (&p, &Fingerprint::new(&p)),
+                (&q, &Fingerprint::new(&q)),
+                MIN_OVERLAP
+            ),
+            None
+        );
+        assert_eq!(resolve(&p, &q, MIN_OVERLAP), None);
+    }
+
+    #[test]
+    fn zero_overlap_means_one_shared_beacon() {
+        let p = scanner_at(Pose::identity(), &cloud(5, 6));
+        let q = scanner_at(pose(2, 10, 20, 30), &cloud(5, 7));
+        let empty = Scanner {
+            positions: Vec::new(),
+        };
+        let aligned = |p: &Scanner, q: &Scanner, min_overlap| {
+            align(
+                (p, &Fingerprint::new(p)),
+                (q, &Fingerprint::new(q)),
+                min_overlap,
+            )
+        };
+
+        for min_overlap in [0, 1] {
+            // Any beacon could be the shared one, so check that the pose found
+            // does line one up.
+            for pose in [aligned(&p, &q, min_overlap), resolve(&p, &q, min_overlap)] {
+                let pose = pose.unwrap();
+                assert!(q
+                    .positions
+                    .iter()
+                    .any(|&b| p.positions.contains(&pose.apply(b))));
+            }
+
+            assert_eq!(aligned(&p, &empty, min_overlap), None);
+            assert_eq!(resolve(&p, &empty, min_overlap), None);
+        }
+    }
+
+    #[test]
+    fn map_recovers_every_pose() {
+        // Each scanner shares 15 beacons with the next, and the last scanner sees
+        // nothing the others do.
+        let beacons = cloud(120, 4);
+        let poses = [
+            Pose::identity(),
+            pose(3, 1000, -200, 50),
+            pose(11, 1800, 900, -400),
+            pose(22, 2500, 100, 1200),
+            pose(9, -5000, 0, 0),
+        ];
+        let seen = [0..35, 20..55, 40..75, 60..95, 95..120];
+        let scanners: Vec<Scanner> = poses
+            .iter()
+            .zip(seen)
+            .map(|(&pose, range)| scanner_at(pose, &beacons[range]))
+            .collect();
+
+        let map = ScannerMap::new(&scanners, MIN_OVERLAP);
+        assert_eq!(
+            map.poses[..4],
+            poses[..4].iter().copied().map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(map.unplaced(), [4]);
+        assert_eq!(
+            map.beacons,
+            beacons[..95].iter().copied().collect::<HashSet<_>>()
+        );
+        assert_eq!(
+            map.scanner_positions().collect::<Vec<_>>(),
+            poses[..4].iter().map(|p| p.translation).collect::<Vec<_>>()
+        );
+
+        // Needing more beacons in common than the scanners share leaves them all
+        // unplaced.
+        let strict = ScannerMap::new(&scanners, 16);
+        assert_eq!(strict.unplaced(), [1, 2, 3, 4]);
+        assert_eq!(strict.beacons.len(), 35);
+    }
+
+    #[test]
+    fn map_places_scanners_through_any_neighbour() {
+        // Scanner 1 only overlaps scanner 2, which is listed after it.
+        let beacons = cloud(60, 5);
+        let poses = [
+            Pose::identity(),
+            pose(1, -700, 20, 0),
+            pose(14, 300, 300, 300),
+        ];
+        let seen = [0..25, 30..60, 10..45];
+        let scanners: Vec<Scanner> = poses
+            .iter()
+            .zip(seen)
+            .map(|(&pose, range)| scanner_at(pose, &beacons[range]))
+            .collect();
+
+        let map = ScannerMap::new(&scanners, MIN_OVERLAP);
+        assert_eq!(map.poses, poses.map(Some));
+        assert_eq!(map.beacons.len(), 60);
+    }
+
+    /// Writes scanners out in the puzzle's input format.
+    fn input(scanners: &[Scanner]) -> Vec<String> {
+        scanners
+            .iter()
+            .enumerate()
+            .map(|(i, scanner)| {
+                std::iter::once(format!("--- scanner {} ---", i))
+                    .chain(
+                        scanner
+                            .positions
+                            .iter()
+                            .map(|b| format!("{},{},{}", b.x, b.y, b.z)),
+                    )
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .join(&String::new())
+    }
+
+    #[test]
+    fn solutions_need_every_scanner_placed() {
+        let beacons = cloud(50, 8);
+        let placed = scanner_at(Pose::identity(), &beacons[..30]);
+        let overlapping = scanner_at(pose(4, 50, -60, 70), &beacons[10..40]);
+        let disconnected = scanner_at(pose(8, 0, 0, 900), &beacons[40..]);
+
+        let lines = ProblemInput::from(input(&[placed.clone(), overlapping]));
+        assert_eq!(Q19.part1(&lines), "40");
+        assert_eq!(Q19.part2(&lines), "180");
+
+        let lines = ProblemInput::from(input(std::slice::from_ref(&placed)));
+        assert_eq!(Q19.part2(&lines), "0");
+
+        let lines = ProblemInput::from(input(&[placed, disconnected]));
+        assert_eq!(
+            place_all(&lines).unwrap_err().to_string(),
+            "scanners [1] don't overlap enough with any placed scanner"
+        );
+    }
+}
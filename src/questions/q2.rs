@@ -1,73 +1,313 @@
-use crate::{FromProblemInputLine, ProblemInput, Solution};
+use crate::{ProblemInput, Solution};
+use anyhow::{anyhow, bail, Result};
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Forward,
+    Back,
     Up,
     Down,
 }
 
 impl FromStr for Direction {
-    type Err = ();
+    type Err = anyhow::Error;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "up" => Ok(Direction::Up),
             "down" => Ok(Direction::Down),
             "forward" => Ok(Direction::Forward),
-            _ => Err(()),
+            "back" => Ok(Direction::Back),
+            _ => Err(anyhow!("unknown command {}", s)),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-struct Movement {
-    direction: Direction,
-    quantity: i64,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub direction: Direction,
+    pub quantity: i64,
 }
 
-impl FromProblemInputLine for Movement {
-    fn from_line(line: &str) -> Self {
-        let parts: Vec<_> = line.split(' ').collect();
-        let direction = Direction::from_str(parts[0]).unwrap();
-        let quantity = parts[1].parse().unwrap();
+impl FromStr for Command {
+    type Err = anyhow::Error;
 
-        Movement {
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+        let (direction, quantity) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(direction), Some(quantity), None) => (direction, quantity),
+            _ => bail!("expected a command and a quantity, got {:?}", s),
+        };
+
+        Ok(Command {
+            direction: direction.parse()?,
+            quantity: quantity.parse()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub horizontal: i64,
+    pub depth: i64,
+}
+
+/// A way of interpreting submarine commands.
+pub trait SubmarineModel {
+    /// Updates the submarine's state in response to a single command.
+    fn apply(&mut self, command: Command);
+
+    /// The submarine's current position.
+    fn position(&self) -> Position;
+}
+
+/// The model from part 1: `up` and `down` change the depth directly.
+#[derive(Debug, Clone, Default)]
+pub struct Simple {
+    position: Position,
+}
+
+impl SubmarineModel for Simple {
+    fn apply(&mut self, command: Command) {
+        let Command {
+            direction,
+            quantity,
+        } = command;
+
+        match direction {
+            Direction::Up => self.position.depth -= quantity,
+            Direction::Down => self.position.depth += quantity,
+            Direction::Forward => self.position.horizontal += quantity,
+            Direction::Back => self.position.horizontal -= quantity,
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.position
+    }
+}
+
+/// The model from part 2: `up` and `down` change the aim, which determines how
+/// the depth changes when moving.
+#[derive(Debug, Clone, Default)]
+pub struct Aimed {
+    position: Position,
+    aim: i64,
+}
+
+impl SubmarineModel for Aimed {
+    fn apply(&mut self, command: Command) {
+        let Command {
             direction,
             quantity,
+        } = command;
+
+        match direction {
+            Direction::Up => self.aim -= quantity,
+            Direction::Down => self.aim += quantity,
+            Direction::Forward => {
+                self.position.horizontal += quantity;
+                self.position.depth += self.aim * quantity;
+            }
+            Direction::Back => {
+                self.position.horizontal -= quantity;
+                self.position.depth -= self.aim * quantity;
+            }
         }
     }
+
+    fn position(&self) -> Position {
+        self.position
+    }
+}
+
+/// The positions a submarine passed through, one for each command.
+#[derive(Debug, Clone, Default)]
+pub struct Track(pub Vec<Position>);
+
+impl Track {
+    /// The final position of the submarine.
+    #[must_use]
+    pub fn end(&self) -> Position {
+        self.0.last().copied().unwrap_or_default()
+    }
+
+    /// Plots this track onto a `width` by `height` grid of characters.
+    #[must_use]
+    pub fn plot(&self, width: usize, height: usize) -> String {
+        plot(&[(self, '#')], width, height)
+    }
+}
+
+/// Plots several tracks onto the same `width` by `height` grid of characters,
+/// with horizontal position increasing to the right and depth increasing
+/// downwards.  Each track is drawn with its own glyph, with later tracks drawn
+/// over earlier ones.
+#[must_use]
+pub fn plot(tracks: &[(&Track, char)], width: usize, height: usize) -> String {
+    if width == 0 || height == 0 {
+        return String::new();
+    }
+
+    // Include the starting position so that the plot is anchored at the surface.
+    let (mut min_h, mut max_h, mut min_d, mut max_d) = (0, 0, 0, 0);
+    for p in tracks.iter().flat_map(|(track, _)| &track.0) {
+        min_h = min_h.min(p.horizontal);
+        max_h = max_h.max(p.horizontal);
+        min_d = min_d.min(p.depth);
+        max_d = max_d.max(p.depth);
+    }
+
+    // Map a coordinate in `lo..=hi` onto one of `cells` cells.
+    let scale = |v: i64, lo: i64, hi: i64, cells: usize| {
+        ((v - lo) as usize * (cells - 1)) / ((hi - lo) as usize).max(1)
+    };
+
+    let mut grid = vec![vec![' '; width]; height];
+    for (track, glyph) in tracks {
+        for p in &track.0 {
+            let x = scale(p.horizontal, min_h, max_h, width);
+            let y = scale(p.depth, min_d, max_d, height);
+            grid[y][x] = *glyph;
+        }
+    }
+
+    grid.into_iter()
+        .map(|row| row.into_iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A list of commands for a submarine to follow.
+#[derive(Debug, Clone)]
+pub struct Course(pub Vec<Command>);
+
+impl Course {
+    /// Parses a course, one command per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line of the first command that couldn't be
+    /// parsed.
+    pub fn new(lines: &ProblemInput) -> Result<Self> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                line.parse().map_err(|e: anyhow::Error| {
+                    e.context(format!("invalid command on line {}", i + 1))
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Course)
+    }
+
+    /// Runs every command through `model`, recording the position after each.
+    pub fn replay<M: SubmarineModel>(&self, mut model: M) -> Track {
+        Track(
+            self.0
+                .iter()
+                .map(|&command| {
+                    model.apply(command);
+                    model.position()
+                })
+                .collect(),
+        )
+    }
+
+    /// Replays this course with two different models, pairing up the positions
+    /// they reach after each command.
+    pub fn compare<A: SubmarineModel, B: SubmarineModel>(
+        &self,
+        a: A,
+        b: B,
+    ) -> Vec<(Position, Position)> {
+        self.replay(a).0.into_iter().zip(self.replay(b).0).collect()
+    }
 }
 
 pub struct Q2;
 
 impl Solution for Q2 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let movements: Vec<Movement> = lines.parse();
+        let course = Course::new(lines).unwrap();
+        let end = course.replay(Simple::default()).end();
 
-        let (h, d) = movements
-            .into_iter()
-            .fold((0, 0), |(h, d), m| match m.direction {
-                Direction::Up => (h, d - m.quantity),
-                Direction::Down => (h, d + m.quantity),
-                Direction::Forward => (h + m.quantity, d),
-            });
-
-        (h * d).to_string()
+        (end.horizontal * end.depth).to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let movements: Vec<Movement> = lines.parse();
+        let course = Course::new(lines).unwrap();
+        let end = course.replay(Aimed::default()).end();
 
-        let (h, d, _) = movements
-            .into_iter()
-            .fold((0, 0, 0), |(h, d, aim), m| match m.direction {
-                Direction::Up => (h, d, aim - m.quantity),
-                Direction::Down => (h, d, aim + m.quantity),
-                Direction::Forward => (h + m.quantity, d + aim * m.quantity, aim),
-            });
+        (end.horizontal * end.depth).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 6] = [
+        "forward 5",
+        "down 5",
+        "forward 8",
+        "up 3",
+        "down 8",
+        "forward 2",
+    ];
+
+    fn at(horizontal: i64, depth: i64) -> Position {
+        Position { horizontal, depth }
+    }
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q2.part1(&lines), "150");
+        assert_eq!(Q2.part2(&lines), "900");
+    }
+
+    #[test]
+    fn example_tracks() {
+        let course = Course::new(&ProblemInput::from(EXAMPLE.to_vec())).unwrap();
+
+        let simple = course.replay(Simple::default());
+        assert_eq!(
+            simple.0,
+            [
+                at(5, 0),
+                at(5, 5),
+                at(13, 5),
+                at(13, 2),
+                at(13, 10),
+                at(15, 10)
+            ]
+        );
+        assert_eq!(simple.plot(4, 3), " ## \n ## \n  ##");
+
+        let compared = course.compare(Simple::default(), Aimed::default());
+        assert_eq!(compared[2], (at(13, 5), at(13, 40)));
+        assert_eq!(compared[5], (at(15, 10), at(15, 60)));
+    }
+
+    #[test]
+    fn back_undoes_forward() {
+        let course =
+            Course::new(&ProblemInput::from(vec!["down 2", "forward 3", "back 3"])).unwrap();
+
+        assert_eq!(course.replay(Simple::default()).end(), at(0, 2));
+        assert_eq!(course.replay(Aimed::default()).end(), at(0, 0));
+    }
+
+    #[test]
+    fn bad_commands_are_rejected() {
+        for line in ["sideways 3", "up", "up 3 4", "down x"] {
+            assert!(Course::new(&ProblemInput::from(vec!["up 1", line])).is_err());
+        }
 
-        (h * d).to_string()
+        let error = Course::new(&ProblemInput::from(vec!["up 1", "left 2"])).unwrap_err();
+        assert_eq!(error.to_string(), "invalid command on line 2");
     }
 }
@@ -1,141 +1,563 @@
 use crate::{FromProblemInput, ProblemInput, Solution};
-use std::cmp::{min, Ordering};
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::{AddAssign, Mul};
 
 pub struct Q21;
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
-struct Pair(i64, i64);
+/// The starting position of each player, in turn order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Starts(pub Vec<i64>);
 
-impl FromProblemInput<'_> for Pair {
+impl FromProblemInput<'_> for Starts {
     fn from(lines: &ProblemInput) -> Self {
-        let positions: Vec<i64> = lines.parse();
-        Pair(positions[1], positions[3])
+        // Each line ends with the player's starting position.
+        Starts(
+            lines
+                .iter()
+                .filter_map(|line| line.split_whitespace().last()?.parse().ok())
+                .collect(),
+        )
     }
 }
 
-trait Die {
-    fn roll(&mut self) -> Vec<i64>;
-    fn roll_count(&self) -> i64 {
-        0
+#[derive(Clone, Debug, Default)]
+struct DeterministicDie {
+    faces: i64,
+    rolls: i64,
+}
+
+impl DeterministicDie {
+    fn roll(&mut self) -> i64 {
+        let result = self.rolls % self.faces + 1;
+        self.rolls += 1;
+        result
     }
 }
 
-#[derive(Clone, Debug, Default)]
-struct DeterministicDie {
-    current: i64,
+/// The end of a game played with a deterministic die.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Practice {
+    /// The final score of each player.
+    pub scores: Vec<i64>,
+    /// The number of times the die was rolled.
+    pub rolls: i64,
 }
 
-impl Die for DeterministicDie {
-    fn roll(&mut self) -> Vec<i64> {
-        let result = self.current + 1;
-        self.current += 1;
-        vec![result]
+/// The rules of a game of Dirac Dice, played by one player for each starting
+/// position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    board_size: i64,
+    rolls_per_turn: usize,
+    die_faces: i64,
+    threshold: i64,
+}
+
+impl Game {
+    /// The practice game from part 1: three rolls of a hundred-sided die per
+    /// turn, on a board of ten spaces, playing to 1000.
+    #[must_use]
+    pub fn practice() -> Self {
+        Self {
+            board_size: 10,
+            rolls_per_turn: 3,
+            die_faces: 100,
+            threshold: 1000,
+        }
     }
 
-    fn roll_count(&self) -> i64 {
-        self.current
+    /// The game from part 2: three rolls of a three-sided die per turn, on a
+    /// board of ten spaces, playing to 21.
+    #[must_use]
+    pub fn quantum() -> Self {
+        Self {
+            die_faces: 3,
+            threshold: 21,
+            ..Self::practice()
+        }
     }
-}
 
-#[derive(Clone, Debug, Default)]
-struct QuantumDie;
+    /// Sets the number of spaces around the board.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `board_size` isn't positive.
+    #[must_use]
+    pub fn with_board_size(mut self, board_size: i64) -> Self {
+        assert!(board_size > 0, "the board must have at least one space");
+        self.board_size = board_size;
+        self
+    }
+
+    #[must_use]
+    pub fn with_rolls_per_turn(mut self, rolls_per_turn: usize) -> Self {
+        self.rolls_per_turn = rolls_per_turn;
+        self
+    }
+
+    /// Sets the number of faces on the die, which are numbered from 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `die_faces` isn't positive.
+    #[must_use]
+    pub fn with_die_faces(mut self, die_faces: i64) -> Self {
+        assert!(die_faces > 0, "the die must have at least one face");
+        self.die_faces = die_faces;
+        self
+    }
+
+    /// Sets the score which a player must reach to win.
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: i64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Moves a pawn forward from `position`, wrapping around the board.
+    fn advance(&self, position: i64, by: i64) -> i64 {
+        (position + by - 1).rem_euclid(self.board_size) + 1
+    }
+
+    /// The number of ways of rolling each possible total in one turn.
+    #[must_use]
+    pub fn roll_sums(&self) -> Vec<(i64, u128)> {
+        let mut ways = HashMap::from([(0, 1)]);
+        for _ in 0..self.rolls_per_turn {
+            let mut next = HashMap::new();
+            for (total, count) in ways {
+                for face in 1..=self.die_faces {
+                    *next.entry(total + face).or_default() += count;
+                }
+            }
+            ways = next;
+        }
+
+        let mut sums: Vec<_> = ways.into_iter().collect();
+        sums.sort_unstable();
+        sums
+    }
+
+    /// Plays a game with a die which rolls 1, 2, 3, and so on, going back to 1
+    /// after its highest face.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no players.
+    #[must_use]
+    pub fn play_deterministic(&self, starts: &Starts) -> Practice {
+        assert!(!starts.0.is_empty(), "a game needs at least one player");
+
+        let mut die = DeterministicDie {
+            faces: self.die_faces,
+            rolls: 0,
+        };
+        let mut positions = starts.0.clone();
+        let mut scores = vec![0; positions.len()];
 
-impl Die for QuantumDie {
-    fn roll(&mut self) -> Vec<i64> {
-        vec![1, 2, 3]
+        for player in (0..positions.len()).cycle() {
+            let total = (0..self.rolls_per_turn).map(|_| die.roll()).sum();
+            positions[player] = self.advance(positions[player], total);
+            scores[player] += positions[player];
+
+            if scores[player] >= self.threshold {
+                break;
+            }
+        }
+
+        Practice {
+            scores,
+            rolls: die.rolls,
+        }
+    }
+
+    /// Counts the universes in which each player wins, when every turn splits
+    /// the universe once for each possible sequence of rolls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no players.
+    #[must_use]
+    pub fn play_quantum(&self, starts: &Starts) -> Vec<u128> {
+        assert!(!starts.0.is_empty(), "a game needs at least one player");
+
+        let mut counter = WinCounter {
+            game: self,
+            sums: self.roll_sums(),
+            memo: HashMap::new(),
+        };
+        let scores = vec![0; starts.0.len()];
+
+        counter.wins(starts.0.clone(), scores, 0)
+    }
+
+    /// Plays the quantum game turn by turn, counting the universes in which each
+    /// player wins on each turn.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no players.
+    #[must_use]
+    pub fn statistics(&self, starts: &Starts) -> Statistics {
+        assert!(!starts.0.is_empty(), "a game needs at least one player");
+
+        let sums = self.roll_sums();
+        let players = starts.0.len();
+        let mut states = HashMap::from([((starts.0.clone(), vec![0; players]), 1)]);
+        let mut endings = Vec::new();
+
+        for player in (0..players).cycle() {
+            if states.is_empty() {
+                break;
+            }
+
+            let mut ended = vec![0; players];
+            let mut next = HashMap::new();
+            for ((positions, scores), count) in states {
+                for &(total, ways) in &sums {
+                    let mut positions = positions.clone();
+                    let mut scores = scores.clone();
+                    positions[player] = self.advance(positions[player], total);
+                    scores[player] += positions[player];
+
+                    if scores[player] >= self.threshold {
+                        ended[player] += count * ways;
+                    } else {
+                        *next.entry((positions, scores)).or_default() += count * ways;
+                    }
+                }
+            }
+
+            endings.push(ended);
+            states = next;
+        }
+
+        Statistics {
+            branches: sums.iter().map(|&(_, ways)| ways).sum(),
+            endings,
+        }
+    }
+
+    /// Finds the probability of the first player winning a two player game, for
+    /// every pair of starting positions.
+    #[must_use]
+    pub fn win_table(&self) -> WinTable {
+        // Weighting each total by its probability rather than its number of
+        // rolls gives the chance of winning instead of the number of wins, and
+        // every pair of starting positions reaches the same states.
+        let branches: u128 = self.roll_sums().iter().map(|&(_, ways)| ways).sum();
+        let mut counter = WinCounter {
+            game: self,
+            sums: self
+                .roll_sums()
+                .into_iter()
+                .map(|(total, ways)| (total, ways as f64 / branches as f64))
+                .collect(),
+            memo: HashMap::new(),
+        };
+
+        let size = self.board_size;
+        let probabilities = (1..=size)
+            .map(|first| {
+                (1..=size)
+                    .map(|second| counter.wins(vec![first, second], vec![0, 0], 0)[0])
+                    .collect()
+            })
+            .collect();
+
+        WinTable(probabilities)
     }
 }
 
-fn _wrap(x: i64) -> i64 {
-    ((x - 1) % 10) + 1
+/// How the universes of a quantum game end.
+///
+/// Universes branch at every turn, so those which end early are each much more
+/// likely than those which end late.  The probabilities here weight each
+/// universe by the chance of its rolls coming up, so they don't match the
+/// proportions of universes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statistics {
+    /// The number of universes each universe splits into on every turn.
+    branches: u128,
+    /// `endings[t][p]` is the number of universes in which player `p` wins on
+    /// turn `t + 1`, where each player's move is a turn.
+    pub endings: Vec<Vec<u128>>,
 }
 
-#[derive(Clone, Debug)]
-struct State {
-    states: HashMap<(Pair, Pair), i64>,
-    terminal: HashMap<Pair, i64>,
-    threshold: i64,
+impl Statistics {
+    /// The number of universes which end on each turn, starting from turn 1.
+    #[must_use]
+    pub fn turns(&self) -> Vec<u128> {
+        self.endings
+            .iter()
+            .map(|ended| ended.iter().sum())
+            .collect()
+    }
+
+    /// The number of universes in which each player wins.
+    #[must_use]
+    pub fn wins(&self) -> Vec<u128> {
+        let players = self.endings.first().map_or(0, Vec::len);
+        (0..players)
+            .map(|player| self.endings.iter().map(|ended| ended[player]).sum())
+            .collect()
+    }
+
+    /// The probability of a game ending on each turn, starting from turn 1.
+    #[must_use]
+    pub fn turn_probabilities(&self) -> Vec<f64> {
+        let branches = self.branches as f64;
+        self.turns()
+            .into_iter()
+            .zip(1..)
+            .map(|(count, turn)| count as f64 / branches.powi(turn))
+            .collect()
+    }
+
+    /// The probability of each player winning.
+    #[must_use]
+    pub fn win_probabilities(&self) -> Vec<f64> {
+        let branches = self.branches as f64;
+        let players = self.endings.first().map_or(0, Vec::len);
+
+        (0..players)
+            .map(|player| {
+                self.endings
+                    .iter()
+                    .zip(1..)
+                    .map(|(ended, turn)| ended[player] as f64 / branches.powi(turn))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// The expected number of turns before somebody wins.
+    #[must_use]
+    pub fn expected_length(&self) -> f64 {
+        self.turn_probabilities()
+            .into_iter()
+            .zip(1..)
+            .map(|(p, turn)| p * f64::from(turn))
+            .sum()
+    }
 }
 
-impl State {
-    fn new(pos: Pair, threshold: i64) -> Self {
-        let states = HashMap::from([((pos, Pair::default()), 1)]);
-        State {
-            states,
-            terminal: HashMap::new(),
-            threshold,
+/// The probability of the first player winning, indexed by the starting
+/// positions of the first and second players (counting from zero).
+#[derive(Clone, Debug, PartialEq)]
+pub struct WinTable(pub Vec<Vec<f64>>);
+
+impl fmt::Display for WinTable {
+    /// Renders the table as percentages, with the first player's starting
+    /// position down the side and the second player's along the top.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "   ")?;
+        for second in 1..=self.0.len() {
+            write!(f, " {:>5}", second)?;
+        }
+        for (first, row) in self.0.iter().enumerate() {
+            write!(f, "\n{:>3}", first + 1)?;
+            for p in row {
+                write!(f, " {:>5.1}", p * 100.0)?;
+            }
         }
+
+        Ok(())
     }
+}
 
-    fn step<D: Die>(&mut self, die: &mut D) -> bool {
-        let states = std::mem::take(&mut self.states);
-
-        for ((pos, score), count) in states.into_iter() {
-            for a in die.roll() {
-                for b in die.roll() {
-                    for c in die.roll() {
-                        let p1_pos = _wrap(pos.0 + a + b + c);
-                        let p1_score = score.0 + p1_pos;
-
-                        if p1_score >= self.threshold {
-                            *self.terminal.entry(Pair(p1_score, score.1)).or_default() += count;
-                            continue;
-                        }
-
-                        for u in die.roll() {
-                            for v in die.roll() {
-                                for w in die.roll() {
-                                    let p2_pos = _wrap(pos.1 + u + v + w);
-                                    let p2_score = score.1 + p2_pos;
-                                    if p2_score >= self.threshold {
-                                        *self
-                                            .terminal
-                                            .entry(Pair(p1_score, p2_score))
-                                            .or_default() += count;
-                                    } else {
-                                        *self
-                                            .states
-                                            .entry((Pair(p1_pos, p2_pos), Pair(p1_score, p2_score)))
-                                            .or_default() += count;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+/// Memoised state for [`Game::play_quantum`] and [`Game::win_table`].
+struct WinCounter<'g, T> {
+    game: &'g Game,
+    /// Each total that can be rolled in a turn, with its weight.
+    sums: Vec<(i64, T)>,
+    memo: HashMap<(Vec<i64>, Vec<i64>, usize), Vec<T>>,
+}
+
+impl<T: Copy + Default + AddAssign + Mul<Output = T>> WinCounter<'_, T> {
+    /// Sums the weights of the ways in which each player goes on to win, from the
+    /// point where `player` is about to move.
+    fn wins(&mut self, positions: Vec<i64>, scores: Vec<i64>, player: usize) -> Vec<T> {
+        let key = (positions, scores, player);
+        if let Some(wins) = self.memo.get(&key) {
+            return wins.clone();
+        }
+        let (positions, scores, _) = &key;
+
+        let mut wins = vec![T::default(); positions.len()];
+        for i in 0..self.sums.len() {
+            let (total, ways) = self.sums[i];
+            let position = self.game.advance(positions[player], total);
+            let score = scores[player] + position;
+
+            if score >= self.game.threshold {
+                wins[player] += ways;
+                continue;
+            }
+
+            let mut next_positions = positions.clone();
+            let mut next_scores = scores.clone();
+            next_positions[player] = position;
+            next_scores[player] = score;
+
+            let next_player = (player + 1) % positions.len();
+            let later = self.wins(next_positions, next_scores, next_player);
+            for (count, later) in wins.iter_mut().zip(later) {
+                *count += ways * later;
             }
         }
 
-        self.states.is_empty()
+        self.memo.insert(key, wins.clone());
+        wins
     }
 }
 
 impl Solution for Q21 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let mut state = State::new(lines.parse(), 1000);
-        let mut die = DeterministicDie::default();
-        while !state.step(&mut die) {}
+        let practice = Game::practice().play_deterministic(&lines.parse());
+        let losing = practice.scores.iter().min().unwrap();
 
-        (state.terminal.keys().next().map(|p| min(p.0, p.1)).unwrap() * die.roll_count())
-            .to_string()
+        (losing * practice.rolls).to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let mut state = State::new(lines.parse(), 21);
-        let mut die = QuantumDie::default();
-        while !state.step(&mut die) {}
+        let wins = Game::quantum().play_quantum(&lines.parse());
+        wins.into_iter().max().unwrap().to_string()
+    }
+}
 
-        state
-            .terminal
-            .into_iter()
-            .fold([0, 0], |[p1_wins, p2_wins], (p, c)| match p.0.cmp(&p.1) {
-                Ordering::Greater | Ordering::Equal => [p1_wins + c, p2_wins],
-                Ordering::Less => [p1_wins, p2_wins + c],
-            })
-            .into_iter()
-            .max()
-            .unwrap()
-            .to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 2] = [
+        "Player 1 starting position: 4",
+        "Player 2 starting position: 8",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q21.part1(&lines), "739785");
+        assert_eq!(Q21.part2(&lines), "444356092776315");
+    }
+
+    #[test]
+    fn example_games() {
+        let starts: Starts = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        assert_eq!(starts, Starts(vec![4, 8]));
+
+        assert_eq!(
+            Game::practice().play_deterministic(&starts),
+            Practice {
+                scores: vec![1000, 745],
+                rolls: 993
+            }
+        );
+        assert_eq!(
+            Game::quantum().play_quantum(&starts),
+            [444_356_092_776_315, 341_960_390_180_808]
+        );
+    }
+
+    #[test]
+    fn roll_sums() {
+        assert_eq!(
+            Game::quantum().roll_sums(),
+            [(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
+        );
+        assert_eq!(
+            Game::quantum()
+                .with_rolls_per_turn(1)
+                .with_die_faces(2)
+                .roll_sums(),
+            [(1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn configurable_games() {
+        // With a one-faced die every universe plays out the same way, stepping
+        // one space at a time around a board of two spaces.
+        let game = Game::quantum()
+            .with_board_size(2)
+            .with_rolls_per_turn(1)
+            .with_die_faces(1)
+            .with_threshold(3);
+
+        assert_eq!(
+            game.play_deterministic(&Starts(vec![1])),
+            Practice {
+                scores: vec![3],
+                rolls: 2
+            }
+        );
+        assert_eq!(game.play_quantum(&Starts(vec![1, 1])), [1, 0]);
+        assert_eq!(
+            game.with_threshold(2).play_quantum(&Starts(vec![2, 1, 1])),
+            [0, 1, 0]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one player")]
+    fn games_need_players() {
+        let _ = Game::quantum().play_quantum(&Starts::default());
+    }
+
+    #[test]
+    fn statistics_agree_with_win_counts() {
+        let starts: Starts = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        let game = Game::quantum();
+        let stats = game.statistics(&starts);
+
+        assert_eq!(stats.wins(), game.play_quantum(&starts));
+
+        // Nobody can reach 21 in fewer than three moves of their own.
+        let turns = stats.turns();
+        assert!(turns[..4].iter().all(|&count| count == 0));
+        assert!(turns[4] > 0);
+
+        let win_total: f64 = stats.win_probabilities().iter().sum();
+        let turn_total: f64 = stats.turn_probabilities().iter().sum();
+        assert!((win_total - 1.0).abs() < 1e-9);
+        assert!((turn_total - 1.0).abs() < 1e-9);
+        assert!((5.0..=turns.len() as f64).contains(&stats.expected_length()));
+    }
+
+    #[test]
+    fn win_table_matches_statistics() {
+        let game = Game::quantum();
+        let table = game.win_table();
+
+        assert_eq!(table.0.len(), 10);
+        assert!(table.0.iter().all(|row| row.len() == 10));
+        for (first, second) in [(4, 8), (1, 1), (10, 3)] {
+            let stats = game.statistics(&Starts(vec![first, second]));
+            let expected = stats.win_probabilities()[0];
+            let actual = table.0[first as usize - 1][second as usize - 1];
+            assert!((actual - expected).abs() < 1e-9);
+        }
+
+        let rendered = table.to_string();
+        assert_eq!(rendered.lines().count(), 11);
+        assert!(rendered.starts_with("        1     2"));
+    }
+
+    #[test]
+    fn win_table_for_a_one_faced_die() {
+        // Both players move one space a turn, so the first player wins unless
+        // their first move only scores 1 while the second player's scores 2.
+        let game = Game::quantum()
+            .with_board_size(2)
+            .with_rolls_per_turn(1)
+            .with_die_faces(1)
+            .with_threshold(2);
+
+        assert_eq!(
+            game.win_table(),
+            WinTable(vec![vec![1.0, 1.0], vec![0.0, 1.0]])
+        );
     }
 }
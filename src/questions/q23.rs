@@ -1,34 +1,28 @@
-use crate::{ProblemInput, Solution};
-use from_iter::FromIterator;
+use crate::visualise::{Discard, Frame, Sink};
+use crate::{FromProblemInput, ProblemInput, Solution};
+use anyhow::{bail, Context, Result};
 use std::cmp::{max, min};
+use std::fmt;
 
 pub struct Q23;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum Token {
-    A,
-    B,
-    C,
-    D,
-}
+/// The two extra side room rows that get unfolded into the diagram for part 2,
+/// with one amphipod for each of the puzzle's four side rooms.
+const UNFOLDED_ROWS: [&str; 2] = ["DCBA", "DBAC"];
+
+/// An amphipod type, identified by the index of the side room it belongs in
+/// (so `A` is `Token(0)`, `B` is `Token(1)` and so on).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token(usize);
 
 impl Token {
-    const fn hallway(self) -> Position {
-        match self {
-            Token::A => Position::Hallway(2),
-            Token::B => Position::Hallway(4),
-            Token::C => Position::Hallway(6),
-            Token::D => Position::Hallway(8),
-        }
+    fn from_char(c: char) -> Option<Self> {
+        c.is_ascii_uppercase()
+            .then(|| Token((c as usize) - ('A' as usize)))
     }
 
-    const fn multiplier(self) -> i64 {
-        match self {
-            Token::A => 1,
-            Token::B => 10,
-            Token::C => 100,
-            Token::D => 1000,
-        }
+    fn to_char(self) -> char {
+        (b'A' + self.0 as u8) as char
     }
 }
 
@@ -39,13 +33,6 @@ pub enum Position {
 }
 
 impl Position {
-    fn hallway(self) -> Position {
-        match self {
-            pos @ Position::Hallway(_) => pos,
-            Position::SideRoom(token, _) => token.hallway(),
-        }
-    }
-
     fn is_side_room(self) -> bool {
         !matches!(self, Position::Hallway(_))
     }
@@ -76,14 +63,104 @@ impl Position {
     }
 }
 
+/// The fixed layout of a burrow: how long the hallway is, where each side room
+/// joins it, and how much energy each amphipod type uses per step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Burrow {
+    hallway_len: usize,
+    entrances: Vec<usize>,
+    costs: Vec<i64>,
+}
+
+impl Burrow {
+    /// Creates a burrow with one side room per entrance.  Amphipod type `i` uses
+    /// `10^i` energy per step, as in the puzzle.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entrance is beyond the end of the hallway, or if
+    /// there are so many side rooms that the energy costs don't fit in an `i64`.
+    pub fn new(hallway_len: usize, entrances: Vec<usize>) -> Result<Self> {
+        if let Some(entrance) = entrances.iter().find(|&&i| i >= hallway_len) {
+            bail!(
+                "side room entrance {} is outside the hallway of length {}",
+                entrance,
+                hallway_len
+            );
+        }
+        let costs = (0..entrances.len() as u32)
+            .map(|i| {
+                10_i64
+                    .checked_pow(i)
+                    .with_context(|| format!("energy cost of amphipod type {} overflows", i))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            hallway_len,
+            entrances,
+            costs,
+        })
+    }
+
+    /// Overrides the per-step energy cost of each amphipod type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one cost per side room.
+    #[must_use]
+    pub fn with_costs(mut self, costs: Vec<i64>) -> Self {
+        assert_eq!(
+            costs.len(),
+            self.entrances.len(),
+            "there must be one cost per side room"
+        );
+        self.costs = costs;
+        self
+    }
+
+    #[must_use]
+    pub fn room_count(&self) -> usize {
+        self.entrances.len()
+    }
+
+    fn entrance(&self, token: Token) -> Position {
+        Position::Hallway(self.entrances[token.0])
+    }
+
+    fn multiplier(&self, token: Token) -> i64 {
+        self.costs[token.0]
+    }
+
+    fn is_entrance(&self, index: usize) -> bool {
+        self.entrances.contains(&index)
+    }
+}
+
+/// A single amphipod moving from one place to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: Position,
+    pub to: Position,
+}
+
+/// The least energy needed to organise the amphipods, along with moves that
+/// organise them using exactly that much.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    pub energy: i64,
+    pub moves: Vec<Move>,
+}
+
 #[derive(Clone, Debug)]
-pub struct State<const N: usize> {
+pub struct State {
+    burrow: Burrow,
     hallway: Hallway,
-    side_rooms: [SideRoom<N>; 4],
+    side_rooms: Vec<SideRoom>,
 }
 
 #[derive(Clone, Debug, Default)]
-pub struct Hallway([Option<Token>; 11]);
+pub struct Hallway(Vec<Option<Token>>);
 
 impl Hallway {
     pub fn iter(&self) -> impl Iterator<Item = (Position, Token)> + '_ {
@@ -94,52 +171,75 @@ impl Hallway {
     }
 }
 
-impl<const N: usize> State<N> {
-    fn new(tokens: [[Token; N]; 4]) -> Self {
-        let _optionify =
-            |x: [Token; N]| <[Option<Token>; N]>::from_iter(x.into_iter().map(Option::Some));
-
-        let side_rooms = [
-            SideRoom {
-                base: Token::A,
-                slots: _optionify(tokens[0]),
-            },
-            SideRoom {
-                base: Token::B,
-                slots: _optionify(tokens[1]),
-            },
-            SideRoom {
-                base: Token::C,
-                slots: _optionify(tokens[2]),
-            },
-            SideRoom {
-                base: Token::D,
-                slots: _optionify(tokens[3]),
-            },
-        ];
+impl State {
+    /// Creates a new state from the contents of each side room, listed from the
+    /// hallway downwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one list of amphipods per side room.
+    #[must_use]
+    pub fn new(burrow: Burrow, tokens: Vec<Vec<Token>>) -> Self {
+        assert_eq!(
+            tokens.len(),
+            burrow.room_count(),
+            "there must be one list of amphipods per side room"
+        );
+
+        let side_rooms = tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, room)| SideRoom {
+                base: Token(i),
+                slots: room.into_iter().map(Option::Some).collect(),
+            })
+            .collect();
+
         Self {
-            hallway: Hallway::default(),
+            hallway: Hallway(vec![None; burrow.hallway_len]),
+            burrow,
             side_rooms,
         }
     }
 
+    /// Unfolds the diagram for part 2, inserting the two hidden rows of
+    /// amphipods below the top row of each side room.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there aren't exactly as many side rooms as the
+    /// hidden rows have amphipods.
+    pub fn unfold(mut self) -> Result<Self> {
+        let rooms = UNFOLDED_ROWS[0].len();
+        if self.side_rooms.len() != rooms {
+            bail!(
+                "only burrows with {} side rooms can be unfolded, not {}",
+                rooms,
+                self.side_rooms.len()
+            );
+        }
+
+        for (i, room) in self.side_rooms.iter_mut().enumerate() {
+            let hidden = UNFOLDED_ROWS
+                .iter()
+                .map(|row| Token::from_char(row.as_bytes()[i] as char));
+            room.slots.splice(1..1, hidden);
+        }
+
+        Ok(self)
+    }
+
     fn get_mut(&mut self, pos: Position) -> &mut Option<Token> {
         match pos {
             Position::Hallway(i) => &mut self.hallway.0[i],
-            Position::SideRoom(Token::A, i) => &mut self.side_rooms[0].slots[i],
-            Position::SideRoom(Token::B, i) => &mut self.side_rooms[1].slots[i],
-            Position::SideRoom(Token::C, i) => &mut self.side_rooms[2].slots[i],
-            Position::SideRoom(Token::D, i) => &mut self.side_rooms[3].slots[i],
+            Position::SideRoom(token, i) => &mut self.side_rooms[token.0].slots[i],
         }
     }
 
     fn get(&self, pos: Position) -> Option<Token> {
         match pos {
             Position::Hallway(i) => self.hallway.0[i],
-            Position::SideRoom(Token::A, i) => self.side_rooms[0].slots[i],
-            Position::SideRoom(Token::B, i) => self.side_rooms[1].slots[i],
-            Position::SideRoom(Token::C, i) => self.side_rooms[2].slots[i],
-            Position::SideRoom(Token::D, i) => self.side_rooms[3].slots[i],
+            Position::SideRoom(token, i) => self.side_rooms[token.0].slots[i],
         }
     }
 
@@ -154,7 +254,7 @@ impl<const N: usize> State<N> {
         }
 
         let mut distance = 0;
-        if src.is_side_room() {
+        if let Position::SideRoom(token, _) = src {
             // move into the hallway
             distance += src.index() as i64 + 1;
 
@@ -163,7 +263,7 @@ impl<const N: usize> State<N> {
                     return None;
                 }
             }
-            src = src.hallway();
+            src = self.burrow.entrance(token);
             if self.get(src).is_some() {
                 return None;
             }
@@ -171,7 +271,7 @@ impl<const N: usize> State<N> {
 
         let hallway_dst = match dst {
             pos @ Position::Hallway(_) => pos,
-            Position::SideRoom(token, _) => token.hallway(),
+            Position::SideRoom(token, _) => self.burrow.entrance(token),
         };
 
         for pos in src.hallway_iter(hallway_dst) {
@@ -197,17 +297,60 @@ impl<const N: usize> State<N> {
         Some(distance)
     }
 
-    fn solve(&mut self) -> i64 {
-        self._solve(0, &mut 100000)
+    /// Returns the least energy required to organise the amphipods, or `None` if
+    /// they can't be organised at all.
+    #[must_use]
+    pub fn solve(&mut self) -> Option<i64> {
+        self.plan().map(|plan| plan.energy)
+    }
+
+    /// Finds the cheapest way to organise the amphipods, or `None` if they can't
+    /// be organised at all.
+    #[must_use]
+    pub fn plan(&mut self) -> Option<Plan> {
+        let mut best = i64::MAX;
+        let mut moves = Vec::new();
+        self._solve(0, &mut best, &mut Vec::new(), &mut moves);
+        if best == i64::MAX {
+            None
+        } else {
+            Some(Plan {
+                energy: best,
+                moves,
+            })
+        }
+    }
+
+    /// Makes a move, returning the energy it uses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if nothing is at `from`, or if the way to `to` is blocked.
+    pub fn apply(&mut self, m: Move) -> i64 {
+        let token = self.get(m.from).expect("no amphipod to move");
+        let steps = self.can_move(m.from, m.to).expect("move is blocked");
+        self.swap(m.from, m.to);
+
+        steps * self.burrow.multiplier(token)
     }
 
-    fn _solve(&mut self, acc: i64, best: &mut i64) -> i64 {
+    /// Searches for the cheapest organisation, keeping the moves taken to reach
+    /// the current state in `path` and the cheapest complete set found so far in
+    /// `plan`.
+    fn _solve(
+        &mut self,
+        acc: i64,
+        best: &mut i64,
+        path: &mut Vec<Move>,
+        plan: &mut Vec<Move>,
+    ) -> i64 {
         if acc >= *best {
             return *best;
         }
 
-        if self.side_rooms.iter().all(|room| room.is_done()) {
+        if self.side_rooms.iter().all(SideRoom::is_done) {
             *best = acc;
+            plan.clone_from(path);
             return acc;
         }
 
@@ -215,13 +358,18 @@ impl<const N: usize> State<N> {
         for (pos, slot) in self.hallway.0.iter().enumerate() {
             if let Some(slot) = *slot {
                 let pos = Position::Hallway(pos);
-                let multiplier = slot.multiplier();
-                for side_room in self.side_rooms.iter() {
+                let multiplier = self.burrow.multiplier(slot);
+                for side_room in &self.side_rooms {
                     if let Some(target_pos) = side_room.free_slot(slot) {
                         if let Some(steps) = self.can_move(pos, target_pos) {
                             let step_cost = multiplier * steps;
                             self.swap(pos, target_pos);
-                            let result = self._solve(acc + step_cost, best);
+                            path.push(Move {
+                                from: pos,
+                                to: target_pos,
+                            });
+                            let result = self._solve(acc + step_cost, best, path, plan);
+                            path.pop();
                             self.swap(target_pos, pos);
                             return result;
                         }
@@ -232,15 +380,17 @@ impl<const N: usize> State<N> {
 
         // Side room -> hallway
         let mut potential_swaps = Vec::new();
-        for side_room in self.side_rooms.iter() {
+        for side_room in &self.side_rooms {
             for (pos, token) in side_room.iter() {
-                if !side_room.is_locked(pos.index()) {
-                    for i in 0..self.hallway.0.len() {
-                        let target_pos = Position::Hallway(i);
-                        if ![2, 4, 6, 8].contains(&i) {
-                            if let Some(steps) = self.can_move(pos, target_pos) {
-                                potential_swaps.push((pos, target_pos, steps * token.multiplier()));
-                            }
+                for i in 0..self.hallway.0.len() {
+                    let target_pos = Position::Hallway(i);
+                    if !self.burrow.is_entrance(i) {
+                        if let Some(steps) = self.can_move(pos, target_pos) {
+                            potential_swaps.push((
+                                pos,
+                                target_pos,
+                                steps * self.burrow.multiplier(token),
+                            ));
                         }
                     }
                 }
@@ -249,7 +399,9 @@ impl<const N: usize> State<N> {
 
         for (src, dst, steps) in potential_swaps {
             self.swap(src, dst);
-            self._solve(acc + steps, best);
+            path.push(Move { from: src, to: dst });
+            self._solve(acc + steps, best, path, plan);
+            path.pop();
             self.swap(dst, src);
         }
 
@@ -257,13 +409,143 @@ impl<const N: usize> State<N> {
     }
 }
 
+impl fmt::Display for State {
+    /// Draws the burrow the same way as the puzzle's diagrams.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell = |token: Option<Token>| token.map_or('.', Token::to_char);
+        let len = self.burrow.hallway_len;
+        let first = self.burrow.entrances.iter().min().copied().unwrap_or(0);
+        let last = self.burrow.entrances.iter().max().copied().unwrap_or(0);
+        let depth = self.side_rooms.first().map_or(0, |room| room.slots.len());
+
+        writeln!(f, "{}", "#".repeat(len + 2))?;
+        writeln!(
+            f,
+            "#{}#",
+            self.hallway.0.iter().map(|&t| cell(t)).collect::<String>()
+        )?;
+
+        // Rows below the hallway, with an extra one for the bottom wall.  The
+        // top row is walled all the way across, and the rest just around the
+        // side rooms.
+        for row in 0..=depth {
+            let line: String = (0..len + 2)
+                .map(|col| {
+                    let room = self.burrow.entrances.iter().position(|&e| e + 1 == col);
+                    match room {
+                        Some(room) if row < depth => cell(self.side_rooms[room].slots[row]),
+                        _ if row == 0 || (first..=last + 2).contains(&col) => '#',
+                        _ => ' ',
+                    }
+                })
+                .collect();
+
+            write!(f, "{}", line.trim_end())?;
+            if row < depth {
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromProblemInput<'_> for State {
+    /// Reads a diagram like the puzzle's, which may have amphipods in the
+    /// hallway and empty spaces in the side rooms.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the diagram has no walled hallway or no side rooms, if it has
+    /// characters other than walls, open spaces and amphipods, if the side
+    /// rooms don't line up with each other or the hallway, if an amphipod has no
+    /// side room of its own, or if there are too many side rooms for their
+    /// energy costs to be represented.
+    fn from(lines: &ProblemInput) -> Self {
+        let cell = |c: char| match c {
+            '.' => None,
+            c => Some(Token::from_char(c).expect("unexpected character in diagram")),
+        };
+
+        // The hallway is everything between the walls on the second line of the
+        // diagram; everything below it is side rooms, one row per line.
+        let hallway_line = lines.lines.get(1).expect("diagram has no hallway");
+        let first_wall = hallway_line.find('#').expect("hallway has no walls");
+        let last_wall = hallway_line.rfind('#').expect("hallway has no walls");
+        assert!(first_wall < last_wall, "hallway has no walls");
+        let hallway_start = first_wall + 1;
+        let hallway: Vec<Option<Token>> = hallway_line[hallway_start..last_wall]
+            .chars()
+            .map(cell)
+            .collect();
+
+        let rows: Vec<Vec<(usize, Option<Token>)>> = lines
+            .iter()
+            .skip(2)
+            .map(|line| {
+                line.char_indices()
+                    .filter(|&(_, c)| c == '.' || c.is_ascii_uppercase())
+                    .map(|(col, c)| (col, cell(c)))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| !row.is_empty())
+            .collect();
+
+        let columns: Vec<usize> = rows
+            .first()
+            .expect("diagram has no side rooms")
+            .iter()
+            .map(|(col, _)| *col)
+            .collect();
+        for row in &rows {
+            assert!(
+                row.iter().map(|(col, _)| *col).eq(columns.iter().copied()),
+                "side room rows don't line up"
+            );
+        }
+
+        let entrances = columns
+            .iter()
+            .map(|col| {
+                col.checked_sub(hallway_start)
+                    .expect("side room is left of the hallway")
+            })
+            .collect();
+        let burrow = Burrow::new(hallway.len(), entrances).unwrap();
+
+        let rooms: Vec<Vec<Option<Token>>> = (0..columns.len())
+            .map(|room| rows.iter().map(|row| row[room].1).collect())
+            .collect();
+        for token in hallway.iter().chain(rooms.iter().flatten()).flatten() {
+            assert!(
+                token.0 < burrow.room_count(),
+                "amphipod {:?} has no side room",
+                token
+            );
+        }
+
+        State {
+            burrow,
+            hallway: Hallway(hallway),
+            side_rooms: rooms
+                .into_iter()
+                .enumerate()
+                .map(|(i, slots)| SideRoom {
+                    base: Token(i),
+                    slots,
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
-struct SideRoom<const N: usize> {
+struct SideRoom {
     base: Token,
-    slots: [Option<Token>; N],
+    slots: Vec<Option<Token>>,
 }
 
-impl<const N: usize> SideRoom<N> {
+impl SideRoom {
     fn iter(&self) -> impl Iterator<Item = (Position, Token)> + '_ {
         self.slots.iter().enumerate().filter_map(|(i, token)| {
             if let Some(token) = *token {
@@ -318,26 +600,133 @@ impl<const N: usize> SideRoom<N> {
     }
 }
 
+/// Solves `state`, then replays the cheapest plan to `sink`.
+///
+/// # Panics
+///
+/// Panics if the amphipods can't be organised.
+fn organise(mut state: State, sink: &dyn Sink) -> i64 {
+    let plan = state
+        .clone()
+        .plan()
+        .expect("the amphipods can't be organised");
+
+    let mut energy = 0;
+    sink.show_with(|| Frame::Text(format!("energy used: {}\n\n{}", energy, state)));
+    for &m in &plan.moves {
+        energy += state.apply(m);
+        sink.show_with(|| Frame::Text(format!("energy used: {}\n\n{}", energy, state)));
+    }
+
+    plan.energy
+}
+
 impl Solution for Q23 {
-    fn part1(&self, _lines: &ProblemInput) -> String {
-        State::new([
-            [Token::A, Token::C],
-            [Token::D, Token::D],
-            [Token::A, Token::B],
-            [Token::C, Token::B],
-        ])
-        .solve()
-        .to_string()
-    }
-
-    fn part2(&self, _lines: &ProblemInput) -> String {
-        State::new([
-            [Token::A, Token::D, Token::D, Token::C],
-            [Token::D, Token::C, Token::B, Token::D],
-            [Token::A, Token::B, Token::A, Token::B],
-            [Token::C, Token::A, Token::C, Token::B],
-        ])
-        .solve()
-        .to_string()
+    fn part1(&self, lines: &ProblemInput) -> String {
+        self.part1_visualised(lines, &Discard)
+    }
+
+    fn part2(&self, lines: &ProblemInput) -> String {
+        self.part2_visualised(lines, &Discard)
+    }
+
+    fn part1_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
+        organise(lines.parse(), sink).to_string()
+    }
+
+    fn part2_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
+        let state: State = lines.parse();
+        organise(state.unfold().unwrap(), sink).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 5] = [
+        "#############",
+        "#...........#",
+        "###B#C#B#D###",
+        "  #A#D#C#A#",
+        "  #########",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q23.part1(&lines), "12521");
+    }
+
+    #[test]
+    #[ignore = "takes minutes without optimisations; run with --release --ignored"]
+    fn example_unfolded() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q23.part2(&lines), "44169");
+    }
+
+    #[test]
+    fn diagram_round_trips() {
+        let state: State = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        assert_eq!(state.to_string(), EXAMPLE.join("\n"));
+    }
+
+    #[test]
+    fn plan_replays_to_an_organised_burrow() {
+        let mut state: State = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        let plan = state.clone().plan().unwrap();
+
+        let energy: i64 = plan.moves.iter().map(|&m| state.apply(m)).sum();
+        assert_eq!(energy, plan.energy);
+        assert!(state.side_rooms.iter().all(SideRoom::is_done));
+    }
+
+    #[test]
+    fn hallway_amphipods_are_kept() {
+        // The example after its first move.
+        let diagram = [
+            "#############",
+            "#...B.......#",
+            "###B#C#.#D###",
+            "  #A#D#C#A#",
+            "  #########",
+        ];
+        let state: State = ProblemInput::from(diagram.to_vec()).parse();
+
+        assert_eq!(state.to_string(), diagram.join("\n"));
+        assert_eq!(Q23.part1(&ProblemInput::from(diagram.to_vec())), "12481");
+    }
+
+    #[test]
+    fn unfolding_inserts_the_hidden_rows() {
+        let state: State = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        assert_eq!(
+            state.unfold().unwrap().to_string(),
+            [
+                "#############",
+                "#...........#",
+                "###B#C#B#D###",
+                "  #D#C#B#A#",
+                "  #D#B#A#C#",
+                "  #A#D#C#A#",
+                "  #########",
+            ]
+            .join("\n")
+        );
+
+        let diagram = ["#########", "#.......#", "###B#A###", "  #A#B#", "  #####"];
+        let state: State = ProblemInput::from(diagram.to_vec()).parse();
+        assert_eq!(state.to_string(), diagram.join("\n"));
+        assert!(state.unfold().is_err());
+    }
+
+    #[test]
+    fn burrow_rejects_bad_layouts() {
+        assert!(Burrow::new(11, vec![2, 4, 6, 11]).is_err());
+
+        // 10^18 is the largest power of ten that fits in an i64.
+        let hallway = 2 * 20 + 1;
+        assert!(Burrow::new(hallway, (0..19).map(|i| 2 * i + 1).collect()).is_ok());
+        assert!(Burrow::new(hallway, (0..20).map(|i| 2 * i + 1).collect()).is_err());
     }
 }
@@ -1,13 +1,138 @@
-use crate::{ProblemInput, Solution};
+use crate::automaton::{Board, Edges, Neighbourhood};
+use crate::visualise::{Discard, Frame, Sink};
+use crate::{FromProblemInput, ProblemInput, Solution};
 
 pub struct Q25;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Cell {
+    Empty,
+    /// A sea cucumber in the east-facing herd.
+    East,
+    /// A sea cucumber in the south-facing herd.
+    South,
+}
+
+impl Cell {
+    fn from_char(c: char) -> Self {
+        match c {
+            '>' => Cell::East,
+            'v' => Cell::South,
+            _ => Cell::Empty,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Cell::Empty => '.',
+            Cell::East => '>',
+            Cell::South => 'v',
+        }
+    }
+}
+
+/// The sea floor, which wraps around at the edges.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SeaFloor(Board<Cell>);
+
+impl SeaFloor {
+    /// Moves every cucumber in `herd` whose way ahead is clear, at the same time.
+    /// `neighbourhood` is the cell behind each cucumber and then the cell ahead.
+    fn advance(&self, herd: Cell, neighbourhood: &Neighbourhood) -> Self {
+        SeaFloor(self.0.step(neighbourhood, |cell, around| {
+            match (cell, around[0], around[1]) {
+                (cell, _, Cell::Empty) if cell == herd => Cell::Empty,
+                (Cell::Empty, behind, _) if behind == herd => herd,
+                (cell, _, _) => cell,
+            }
+        }))
+    }
+
+    /// Moves the east-facing herd, and then the south-facing herd.
+    fn step(&self) -> Self {
+        let east = Neighbourhood::new(vec![(-1, 0), (1, 0)]);
+        let south = Neighbourhood::new(vec![(0, -1), (0, 1)]);
+
+        self.advance(Cell::East, &east).advance(Cell::South, &south)
+    }
+}
+
+impl FromProblemInput<'_> for SeaFloor {
+    fn from(lines: &ProblemInput) -> Self {
+        let width = lines.lines.first().map_or(0, String::len);
+        let cells = lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .map(Cell::from_char)
+            .collect();
+
+        SeaFloor(Board::new(width, lines.len(), cells, Edges::Toroidal))
+    }
+}
+
 impl Solution for Q25 {
-    fn part1(&self, _lines: &ProblemInput) -> String {
-        String::new()
+    fn part1(&self, lines: &ProblemInput) -> String {
+        self.part1_visualised(lines, &Discard)
+    }
+
+    fn part1_visualised(&self, lines: &ProblemInput, sink: &dyn Sink) -> String {
+        let mut floor: SeaFloor = lines.parse();
+        let frame = |step: usize, floor: &SeaFloor| {
+            Frame::Text(format!(
+                "after step {}\n\n{}",
+                step,
+                floor.0.render(Cell::to_char)
+            ))
+        };
+        sink.show_with(|| frame(0, &floor));
+
+        // Find the first step on which nothing moves.
+        let mut step = 1;
+        loop {
+            let next = floor.step();
+            if next == floor {
+                return step.to_string();
+            }
+
+            floor = next;
+            sink.show_with(|| frame(step, &floor));
+            step += 1;
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 9] = [
+        "v...>>.vv>",
+        ".vv>>.vv..",
+        ">>.>v>...v",
+        ">>v>>.>.v.",
+        "v>v.vv.v..",
+        ">.>>..v...",
+        ".vv..>.>v.",
+        "v.v..>>v.v",
+        "....v..v.>",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q25.part1(&lines), "58");
+    }
+
+    #[test]
+    fn herds_move_in_turn_and_wrap() {
+        let lines = ProblemInput::from(vec![
+            "...>...", ".......", "......>", "v.....>", "......>", ".......", "..vvv..",
+        ]);
+        let floor: SeaFloor = lines.parse();
 
-    fn part2(&self, _lines: &ProblemInput) -> String {
-        String::new()
+        assert_eq!(
+            floor.step().0.render(Cell::to_char),
+            "..vv>..\n.......\n>......\nv.....>\n>......\n.......\n....v.."
+        );
     }
 }
@@ -1,78 +1,285 @@
-use crate::{Digits, ProblemInput, Solution};
+use crate::{ProblemInput, Solution};
+use anyhow::{bail, Result};
+use num::BigUint;
 
 pub struct Q3;
 
-/// Returns an iterator over the `col`th element of each entry in `items`.
-fn iter_col<T: Copy>(items: &[Vec<T>], col: usize) -> impl Iterator<Item = T> + '_ {
-    items.iter().map(move |row| row[col])
+/// Which of the two life support ratings to compute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rating {
+    /// Keep the most common bit in each column, preferring ones on a tie.
+    Oxygen,
+    /// Keep the least common bit in each column, preferring zeroes on a tie.
+    Scrubber,
 }
 
-/// Returns whether the number of ones in `digits` is greater than or equal to
-/// the number of zeroes..
-fn ones_geq_zeroes<I: IntoIterator<Item = u32>>(digits: I) -> bool {
-    let (mut zeroes, mut ones) = (0, 0);
+/// A diagnostic report of equal-width binary numbers.
+///
+/// Each line is packed into `limbs` 64-bit words, most significant word first
+/// and right-aligned, so comparing the words of two lines compares their values.
+#[derive(Clone, Debug)]
+pub struct Report {
+    width: usize,
+    limbs: usize,
+    bits: Vec<u64>,
+}
+
+impl Report {
+    /// Parses a report consisting of one binary number per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report is empty, contains characters other than
+    /// `0` and `1`, or has lines of differing widths.
+    pub fn new(lines: &ProblemInput) -> Result<Self> {
+        let width = match lines.iter().next() {
+            Some(line) if !line.is_empty() => line.len(),
+            _ => bail!("diagnostic report is empty"),
+        };
+        let limbs = num::integer::div_ceil(width, 64);
+        let pad = limbs * 64 - width;
+
+        let mut bits = vec![0; limbs * lines.len()];
+        for (row, line) in lines.iter().enumerate() {
+            if line.len() != width {
+                bail!(
+                    "line {} has width {}, expected {}",
+                    row + 1,
+                    line.len(),
+                    width
+                );
+            }
 
-    for item in digits {
-        if item == 0 {
-            zeroes += 1;
-        } else if item == 1 {
-            ones += 1;
+            let packed = &mut bits[row * limbs..(row + 1) * limbs];
+            for (col, c) in line.chars().enumerate() {
+                match c {
+                    '0' => {}
+                    '1' => packed[(pad + col) / 64] |= 1 << (63 - (pad + col) % 64),
+                    _ => bail!("invalid character {} on line {}", c, row + 1),
+                }
+            }
         }
+
+        Ok(Self { width, limbs, bits })
     }
 
-    ones >= zeroes
-}
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
 
-fn parse(lines: &ProblemInput) -> Vec<Vec<u32>> {
-    lines
-        .parse::<Vec<Digits>>()
-        .into_iter()
-        .map(|d| d.digits)
-        .collect()
-}
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bits.len() / self.limbs
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &[u64]> {
+        self.bits.chunks_exact(self.limbs)
+    }
+
+    /// Returns the bit in column `col` (counting from the left) of `line`.
+    fn bit(&self, line: &[u64], col: usize) -> bool {
+        let pos = self.limbs * 64 - self.width + col;
+        line[pos / 64] & (1 << (63 - pos % 64)) != 0
+    }
 
-fn filter(mut items: Vec<Vec<u32>>, flip: bool) -> i32 {
-    // Iterate through each column until there is one item remaining.
-    for col in 0..items[0].len() {
-        if items.len() == 1 {
-            break;
+    /// Returns the number of ones in each column.
+    fn column_ones(&self) -> Vec<usize> {
+        let pad = self.limbs * 64 - self.width;
+        let mut ones = vec![0; self.width];
+
+        for line in self.lines() {
+            for (i, &limb) in line.iter().enumerate() {
+                let mut limb = limb;
+                while limb != 0 {
+                    let pos = i * 64 + 63 - limb.trailing_zeros() as usize;
+                    ones[pos - pad] += 1;
+                    limb &= limb - 1;
+                }
+            }
         }
 
-        // Shrink `items` down to only those items having the specified digit
-        // in this column.
-        let keep_ones = ones_geq_zeroes(iter_col(&items, col)) ^ flip;
-        items.retain(|row| row[col] == u32::from(keep_ones));
+        ones
     }
 
-    assert_eq!(items.len(), 1);
+    /// Returns the gamma and epsilon rates, built from the most and least common
+    /// bit in each column respectively.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any column has as many ones as zeroes.
+    pub fn power_rates(&self) -> Result<(BigUint, BigUint)> {
+        let mut gamma = BigUint::default();
+        let mut epsilon = BigUint::default();
+
+        for (col, ones) in self.column_ones().into_iter().enumerate() {
+            let zeroes = self.len() - ones;
+            if ones == zeroes {
+                bail!("column {} has equally many ones and zeroes", col);
+            }
+
+            gamma = (gamma << 1_usize) + u32::from(ones > zeroes);
+            epsilon = (epsilon << 1_usize) + u32::from(ones < zeroes);
+        }
+
+        Ok((gamma, epsilon))
+    }
+
+    /// Computes one of the life support ratings.
+    ///
+    /// With the lines sorted, every set of lines sharing a prefix is a contiguous
+    /// range, and within that range the lines with a zero in the next column all
+    /// come before those with a one.  Each round of filtering is then just a
+    /// binary search which narrows the range.
+    #[must_use]
+    pub fn rating(&self, rating: Rating) -> BigUint {
+        let mut lines: Vec<_> = self.lines().collect();
+        lines.sort_unstable();
+
+        let (mut lo, mut hi) = (0, lines.len());
+        for col in 0..self.width {
+            if hi - lo == 1 {
+                break;
+            }
 
-    // Convert the one remaining item from a vec of binary digits to a decimal number
-    items[0].iter().fold(0, |acc, &v| 2 * acc + (v as i32))
+            let mid = lo + lines[lo..hi].partition_point(|line| !self.bit(line, col));
+            let (zeroes, ones) = (mid - lo, hi - mid);
+            if zeroes == 0 || ones == 0 {
+                // Every remaining line agrees on this column.
+                continue;
+            }
+
+            let keep_ones = match rating {
+                Rating::Oxygen => ones >= zeroes,
+                Rating::Scrubber => ones < zeroes,
+            };
+            if keep_ones {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // Anything left over is identical, so just take the first line.
+        lines[lo]
+            .iter()
+            .fold(BigUint::default(), |acc, &limb| (acc << 64_usize) + limb)
+    }
 }
 
 impl Solution for Q3 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let digits = parse(lines);
-
-        // This is both hilarious and terrible.
-        let (gamma, epsilon) = (0..digits[0].len())
-            .map(|col| ones_geq_zeroes(iter_col(&digits, col)))
-            .fold((0, 0), |(gamma, epsilon), digit| {
-                (
-                    (gamma * 2) + u32::from(digit),
-                    (epsilon * 2) + (1 - u32::from(digit)),
-                )
-            });
+        let report = Report::new(lines).unwrap();
+        let (gamma, epsilon) = report.power_rates().unwrap();
 
         (gamma * epsilon).to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let digits = parse(lines);
+        let report = Report::new(lines).unwrap();
 
-        let oxygen = filter(digits.clone(), false);
-        let scrubber = filter(digits, true);
+        let oxygen = report.rating(Rating::Oxygen);
+        let scrubber = report.rating(Rating::Scrubber);
 
         (oxygen * scrubber).to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Random;
+
+    const EXAMPLE: [&str; 12] = [
+        "00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000", "11001",
+        "00010", "01010",
+    ];
+
+    /// Filters the lines column by column as the puzzle describes.
+    fn rating_reference(lines: &[String], rating: Rating) -> BigUint {
+        let mut lines = lines.to_vec();
+        let mut col = 0;
+        while lines.len() > 1 {
+            let ones = lines.iter().filter(|l| l.as_bytes()[col] == b'1').count();
+            let zeroes = lines.len() - ones;
+            let keep = match rating {
+                Rating::Oxygen => u8::from(ones >= zeroes),
+                Rating::Scrubber => u8::from(ones < zeroes),
+            };
+            lines.retain(|l| l.as_bytes()[col] == b'0' + keep);
+            col += 1;
+        }
+        BigUint::parse_bytes(lines[0].as_bytes(), 2).unwrap()
+    }
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q3.part1(&lines), "198");
+        assert_eq!(Q3.part2(&lines), "230");
+
+        let report = Report::new(&lines).unwrap();
+        assert_eq!(
+            report.power_rates().unwrap(),
+            (BigUint::from(22_u32), BigUint::from(9_u32))
+        );
+        assert_eq!(report.rating(Rating::Oxygen), BigUint::from(23_u32));
+        assert_eq!(report.rating(Rating::Scrubber), BigUint::from(10_u32));
+    }
+
+    #[test]
+    fn wide_reports_match_filtering() {
+        // Pseudo-random 100 bit lines, so that they span two limbs.
+        let mut rng = Random::new(7);
+        let lines: Vec<String> = (0..31)
+            .map(|_| {
+                (0..100)
+                    .map(|_| if rng.coin() { '1' } else { '0' })
+                    .collect()
+            })
+            .collect();
+        let report = Report::new(&ProblemInput::from(
+            lines.iter().map(String::as_str).collect::<Vec<_>>(),
+        ))
+        .unwrap();
+
+        assert_eq!(report.width(), 100);
+        assert_eq!(report.len(), 31);
+
+        // An odd number of lines means no column can be tied.
+        let gamma: String = (0..100)
+            .map(|col| {
+                let ones = lines.iter().filter(|l| l.as_bytes()[col] == b'1').count();
+                if ones * 2 > lines.len() {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect();
+        let (g, e) = report.power_rates().unwrap();
+        assert_eq!(g, BigUint::parse_bytes(gamma.as_bytes(), 2).unwrap());
+        assert_eq!(g + e, (BigUint::from(1_u32) << 100_usize) - 1_u32);
+
+        for rating in [Rating::Oxygen, Rating::Scrubber] {
+            assert_eq!(report.rating(rating), rating_reference(&lines, rating));
+        }
+    }
+
+    #[test]
+    fn bad_reports_are_rejected() {
+        assert!(Report::new(&ProblemInput::from(Vec::<&str>::new())).is_err());
+        assert!(Report::new(&ProblemInput::from(vec!["101", "10"])).is_err());
+        assert!(Report::new(&ProblemInput::from(vec!["101", "1x1"])).is_err());
+
+        let tied = Report::new(&ProblemInput::from(vec!["10", "01", "11"])).unwrap();
+        assert!(tied.power_rates().is_ok());
+        let tied = Report::new(&ProblemInput::from(vec!["10", "01"])).unwrap();
+        assert!(tied.power_rates().is_err());
+    }
+}
@@ -2,103 +2,260 @@ use crate::{FromProblemInput, ProblemInput, Skip, Solution};
 
 pub struct Q4;
 
-struct BingoGame {
-    draws: Vec<i64>,
-    boards: Vec<Vec<Vec<i64>>>,
-    completed_boards: Vec<Vec<Vec<i64>>>,
+#[derive(Debug, Clone)]
+pub struct Board {
+    numbers: Vec<Vec<i64>>,
+    marked: Vec<Vec<bool>>,
 }
 
-impl BingoGame {
-    /// Returns a reference to the board that won most recently.
-    fn most_recent_winner(&self) -> &Vec<Vec<i64>> {
-        self.completed_boards.last().unwrap()
-    }
+impl Board {
+    /// Creates an unmarked board from its rows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the board is empty or its rows don't all have the same length.
+    #[must_use]
+    pub fn new(numbers: Vec<Vec<i64>>) -> Self {
+        assert!(
+            !numbers.is_empty() && numbers.iter().all(|row| row.len() == numbers[0].len()),
+            "bingo boards must be rectangular"
+        );
+        let marked = numbers.iter().map(|row| vec![false; row.len()]).collect();
 
-    /// Has at least one board won?
-    fn has_complete_boards(&self) -> bool {
-        !self.completed_boards.is_empty()
+        Self { numbers, marked }
     }
 
-    /// Are there any boards that haven't won yet?
-    fn has_incomplete_boards(&self) -> bool {
-        !self.boards.is_empty()
+    fn rows(&self) -> usize {
+        self.numbers.len()
     }
 
-    /// Draws a new number, marking off any boards containing the number.  Returns the drawn number.
-    fn draw(&mut self) -> i64 {
-        fn is_winning_board(board: &[Vec<i64>]) -> bool {
-            board.iter().any(|row| row.iter().sum::<i64>() == 0)
-                || (0..board.len())
-                    .any(|col| (0..board.len()).map(|row| board[row][col]).sum::<i64>() == 0)
-        }
+    fn cols(&self) -> usize {
+        self.numbers[0].len()
+    }
 
-        fn mark(board: &mut Vec<Vec<i64>>, target: i64) {
-            for row in board.iter_mut() {
-                for val in row.iter_mut() {
-                    if *val == target {
-                        *val = 0;
-                    }
+    /// Marks off every occurrence of `target` on this board.
+    fn mark(&mut self, target: i64) {
+        for (row, marked) in self.numbers.iter().zip(self.marked.iter_mut()) {
+            for (val, marked) in row.iter().zip(marked.iter_mut()) {
+                if *val == target {
+                    *marked = true;
                 }
             }
         }
+    }
+
+    /// Has every number in some row or column been marked?  If `diagonals` is set
+    /// then the two diagonals of a square board also count.
+    fn is_winning(&self, diagonals: bool) -> bool {
+        let (rows, cols) = (self.rows(), self.cols());
+        let marked = |row: usize, col: usize| self.marked[row][col];
+
+        let any_row = (0..rows).any(|row| (0..cols).all(|col| marked(row, col)));
+        let any_col = (0..cols).any(|col| (0..rows).all(|row| marked(row, col)));
+        let any_diagonal = diagonals
+            && rows == cols
+            && ((0..rows).all(|i| marked(i, i)) || (0..rows).all(|i| marked(i, cols - 1 - i)));
+
+        any_row || any_col || any_diagonal
+    }
+
+    fn unmarked_sum(&self) -> i64 {
+        self.numbers
+            .iter()
+            .flatten()
+            .zip(self.marked.iter().flatten())
+            .filter(|(_, &marked)| !marked)
+            .map(|(val, _)| *val)
+            .sum()
+    }
+}
 
-        let target = self.draws.pop().unwrap();
-        for board in &mut self.boards {
-            mark(board, target);
+/// A single board winning during a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Win {
+    /// The index of the board that won.
+    pub board: usize,
+    /// The index of the draw the board won on.
+    pub draw: usize,
+    /// The number that was drawn.
+    pub number: i64,
+    /// The sum of the unmarked numbers on the board, multiplied by `number`.
+    pub score: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BingoGame {
+    draws: Vec<i64>,
+    boards: Vec<Board>,
+    diagonals: bool,
+}
+
+impl BingoGame {
+    #[must_use]
+    pub fn new(draws: Vec<i64>, boards: Vec<Board>) -> Self {
+        Self {
+            draws,
+            boards,
+            diagonals: false,
         }
+    }
 
-        let completed_boards: Vec<_> = self
-            .boards
-            .drain_filter(|board| is_winning_board(board))
-            .collect();
-        self.completed_boards.extend(completed_boards);
+    /// Sets whether a completely marked diagonal wins on square boards.
+    #[must_use]
+    pub fn with_diagonals(mut self, diagonals: bool) -> Self {
+        self.diagonals = diagonals;
+        self
+    }
+
+    /// Plays through every draw, returning each board's win in the order they
+    /// happened.  A board stops playing once it has won, and boards which never
+    /// win don't appear.
+    #[must_use]
+    pub fn timeline(&self) -> Vec<Win> {
+        let mut boards: Vec<_> = self.boards.iter().cloned().enumerate().collect();
+        let mut timeline = Vec::new();
+
+        for (draw, &number) in self.draws.iter().enumerate() {
+            for (_, board) in &mut boards {
+                board.mark(number);
+            }
 
-        target
+            boards.retain(|(index, board)| {
+                if board.is_winning(self.diagonals) {
+                    timeline.push(Win {
+                        board: *index,
+                        draw,
+                        number,
+                        score: board.unmarked_sum() * number,
+                    });
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        timeline
     }
 }
 
 impl FromProblemInput<'_> for BingoGame {
     fn from(lines: &ProblemInput) -> Self {
-        let mut draws: Vec<i64> = lines.split(0..=0).parse();
-        draws.reverse(); // reverse once so we're popping initial draws off the end
+        let draws: Vec<i64> = lines.split(0..=0).parse();
 
         // Now parse the boards
         let boards: Skip<Vec<Vec<i64>>> = lines.split(2..).parse();
 
-        BingoGame {
-            draws,
-            boards: boards.unwrap(),
-            completed_boards: Vec::new(),
-        }
-    }
-}
+        let boards = boards
+            .unwrap()
+            .into_iter()
+            .filter(|board| !board.is_empty())
+            .map(Board::new)
+            .collect();
 
-fn sum(board: &[Vec<i64>]) -> i64 {
-    board.iter().flatten().sum()
+        BingoGame::new(draws, boards)
+    }
 }
 
 impl Solution for Q4 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let mut game: BingoGame = lines.parse();
+        let game: BingoGame = lines.parse();
+        game.timeline().first().unwrap().score.to_string()
+    }
 
-        let mut last_drawn = 0;
-        while !game.has_complete_boards() {
-            last_drawn = game.draw();
-        }
+    fn part2(&self, lines: &ProblemInput) -> String {
+        let game: BingoGame = lines.parse();
+        game.timeline().last().unwrap().score.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let winning_board = game.most_recent_winner();
-        (sum(winning_board) * last_drawn).to_string()
+    const EXAMPLE: [&str; 19] = [
+        "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1",
+        "",
+        "22 13 17 11  0",
+        " 8  2 23  4 24",
+        "21  9 14 16  7",
+        " 6 10  3 18  5",
+        " 1 12 20 15 19",
+        "",
+        " 3 15  0  2 22",
+        " 9 18 13 17  5",
+        "19  8  7 25 23",
+        "20 11 10 24  4",
+        "14 21 16 12  6",
+        "",
+        "14 21 17 24  4",
+        "10 16 15  9 19",
+        "18  8 23 26 20",
+        "22 11 13  6  5",
+        " 2  0 12  3  7",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q4.part1(&lines), "4512");
+        assert_eq!(Q4.part2(&lines), "1924");
     }
 
-    fn part2(&self, lines: &ProblemInput) -> String {
-        let mut game: BingoGame = lines.parse();
+    #[test]
+    fn example_timeline() {
+        let game: BingoGame = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        let timeline = game.timeline();
 
-        let mut last_drawn = 0;
-        while game.has_incomplete_boards() {
-            last_drawn = game.draw();
-        }
+        assert_eq!(
+            timeline.iter().map(|win| win.board).collect::<Vec<_>>(),
+            [2, 0, 1]
+        );
+        assert_eq!(
+            timeline[0],
+            Win {
+                board: 2,
+                draw: 11,
+                number: 24,
+                score: 4512
+            }
+        );
+        assert_eq!(timeline[2].number, 13);
+    }
+
+    #[test]
+    fn zero_is_not_marked_until_drawn() {
+        let game = BingoGame::new(
+            vec![1, 2, 0],
+            vec![Board::new(vec![vec![0, 1, 2], vec![3, 4, 5]])],
+        );
+
+        assert_eq!(
+            game.timeline(),
+            [Win {
+                board: 0,
+                draw: 2,
+                number: 0,
+                score: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn rectangular_boards_and_diagonals() {
+        let board = Board::new(vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        let game = BingoGame::new(vec![1, 3, 5], vec![board]);
+        assert_eq!(game.timeline()[0].score, 5 * (2 + 4 + 6));
+
+        let square = Board::new(vec![vec![1, 2], vec![3, 4]]);
+        let game = BingoGame::new(vec![1, 4], vec![square]);
+        assert!(game.timeline().is_empty());
+        assert_eq!(game.with_diagonals(true).timeline()[0].score, 4 * (2 + 3));
+    }
 
-        let winning_board = game.most_recent_winner();
-        (sum(winning_board) * last_drawn).to_string()
+    #[test]
+    #[should_panic(expected = "bingo boards must be rectangular")]
+    fn ragged_boards_are_rejected() {
+        let _ = Board::new(vec![vec![1, 2], vec![3]]);
     }
 }
@@ -1,10 +1,12 @@
 use crate::{FromProblemInputLine, ProblemInput, Solution};
+use anyhow::{bail, Result};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 pub struct Q5;
 
+/// The points from `point` to `target` inclusive, stepping by `(dx, dy)`.
 struct PointIter {
-    point: (i64, i64),
+    point: Option<(i64, i64)>,
     dx: i64,
     dy: i64,
     target: (i64, i64),
@@ -14,22 +16,25 @@ impl Iterator for PointIter {
     type Item = (i64, i64);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.point == self.target {
-            return None;
-        }
+        let point = self.point?;
+        self.point = (point != self.target).then(|| (point.0 + self.dx, point.1 + self.dy));
 
-        self.point = (self.point.0 + self.dx, self.point.1 + self.dy);
-        Some(self.point)
+        Some(point)
     }
 }
 
 #[derive(Copy, Clone, Debug)]
-struct Line {
+pub struct Line {
     src: (i64, i64),
     dst: (i64, i64),
 }
 
 impl Line {
+    #[must_use]
+    pub fn new(src: (i64, i64), dst: (i64, i64)) -> Self {
+        Self { src, dst }
+    }
+
     fn dx(&self) -> i64 {
         match self.dst.0.cmp(&self.src.0) {
             Ordering::Greater => 1,
@@ -48,7 +53,7 @@ impl Line {
 
     fn iter(&self) -> impl Iterator<Item = (i64, i64)> {
         PointIter {
-            point: (self.src.0 - self.dx(), self.src.1 - self.dy()),
+            point: Some(self.src),
             dx: self.dx(),
             dy: self.dy(),
             target: self.dst,
@@ -58,6 +63,200 @@ impl Line {
     fn is_diagonal(&self) -> bool {
         self.src.0 != self.dst.0 && self.src.1 != self.dst.1
     }
+
+    /// Describes this line as an interval along one of the lines of its family.
+    fn interval(&self) -> Result<(Family, i64, i64, i64)> {
+        let ((x0, y0), (x1, y1)) = (self.src, self.dst);
+
+        let family = if y0 == y1 {
+            Family::Horizontal
+        } else if x0 == x1 {
+            Family::Vertical
+        } else if x1 - x0 == y1 - y0 {
+            Family::Diagonal
+        } else if x1 - x0 == y0 - y1 {
+            Family::AntiDiagonal
+        } else {
+            bail!("{:?} is not horizontal, vertical or at 45 degrees", self);
+        };
+
+        let (key, t0) = family.coordinates(self.src);
+        let (_, t1) = family.coordinates(self.dst);
+        Ok((family, key, t0.min(t1), t0.max(t1)))
+    }
+}
+
+/// The four directions a line can run in.  Every point on a line of a given
+/// family is described by a `key` (which line of the family it's on) and a
+/// position `t` along that line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Family {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    AntiDiagonal,
+}
+
+impl Family {
+    fn coordinates(self, (x, y): (i64, i64)) -> (i64, i64) {
+        match self {
+            Family::Horizontal => (y, x),
+            Family::Vertical => (x, y),
+            Family::Diagonal => (x - y, x),
+            Family::AntiDiagonal => (x + y, x),
+        }
+    }
+
+    /// Returns the point where line `k1` of this family crosses line `k2` of
+    /// `other`, if the families differ and that point has integer coordinates.
+    fn crossing(self, k1: i64, other: Family, k2: i64) -> Option<(i64, i64)> {
+        use Family::{AntiDiagonal, Diagonal, Horizontal, Vertical};
+
+        match (self, other) {
+            (Horizontal, Vertical) => Some((k2, k1)),
+            (Horizontal, Diagonal) => Some((k2 + k1, k1)),
+            (Horizontal, AntiDiagonal) => Some((k2 - k1, k1)),
+            (Vertical, Diagonal) => Some((k1, k1 - k2)),
+            (Vertical, AntiDiagonal) => Some((k1, k2 - k1)),
+            (Diagonal, AntiDiagonal) => {
+                ((k1 + k2) % 2 == 0).then(|| ((k1 + k2) / 2, (k2 - k1) / 2))
+            }
+            (a, b) if a == b => None,
+            (a, b) => b.crossing(k2, a, k1),
+        }
+    }
+}
+
+/// A maximal run `t0..=t1` along one line where exactly `count` lines overlap.
+#[derive(Copy, Clone, Debug)]
+struct Run {
+    t0: i64,
+    t1: i64,
+    count: usize,
+}
+
+/// Sweeps along a single line, merging the given intervals into runs of
+/// constant (non-zero) coverage.
+fn sweep(intervals: &[(i64, i64)]) -> Vec<Run> {
+    let mut events: Vec<_> = intervals
+        .iter()
+        .flat_map(|&(t0, t1)| [(t0, 1), (t1 + 1, -1)])
+        .collect();
+    events.sort_unstable();
+
+    let mut runs = Vec::new();
+    let mut count: i64 = 0;
+    let mut start = 0;
+    for (t, delta) in events {
+        if t != start && count > 0 {
+            runs.push(Run {
+                t0: start,
+                t1: t - 1,
+                count: count as usize,
+            });
+        }
+        start = t;
+        count += delta;
+    }
+
+    runs
+}
+
+/// Counts the points covered by at least `threshold` of `lines` without visiting
+/// every point.
+///
+/// Overlapping lines of the same family are merged with a sweep along each line
+/// of that family.  The only points that need individual attention are those
+/// where lines of different families cross; there are at most quadratically many
+/// of those in the number of runs, regardless of how long the lines are.
+///
+/// # Errors
+///
+/// Returns an error if any of the lines isn't horizontal, vertical or at 45°.
+///
+/// # Panics
+///
+/// Panics if `threshold` is zero, as infinitely many points are covered by no
+/// lines at all.
+pub fn count_overlaps(lines: &[Line], threshold: usize) -> Result<usize> {
+    assert!(threshold > 0, "threshold must be at least one");
+
+    let mut intervals: HashMap<(Family, i64), Vec<(i64, i64)>> = HashMap::new();
+    for line in lines {
+        let (family, key, t0, t1) = line.interval()?;
+        intervals.entry((family, key)).or_default().push((t0, t1));
+    }
+
+    let runs: HashMap<(Family, i64), Vec<Run>> = intervals
+        .into_iter()
+        .map(|(line, intervals)| (line, sweep(&intervals)))
+        .collect();
+
+    // Count the points each family covers on its own.
+    let mut total: usize = runs
+        .values()
+        .flatten()
+        .filter(|run| run.count >= threshold)
+        .map(|run| (run.t1 - run.t0 + 1) as usize)
+        .sum();
+
+    // Find every point covered by more than one family.
+    let all_runs: Vec<_> = runs
+        .iter()
+        .flat_map(|(&(family, key), runs)| runs.iter().map(move |run| (family, key, *run)))
+        .collect();
+    let mut crossings = HashSet::new();
+    for (i, &(f1, k1, r1)) in all_runs.iter().enumerate() {
+        for &(f2, k2, r2) in &all_runs[i + 1..] {
+            if let Some(point) = f1.crossing(k1, f2, k2) {
+                let (_, t1) = f1.coordinates(point);
+                let (_, t2) = f2.coordinates(point);
+                if (r1.t0..=r1.t1).contains(&t1) && (r2.t0..=r2.t1).contains(&t2) {
+                    crossings.insert(point);
+                }
+            }
+        }
+    }
+
+    // The per-family counts above considered each family in isolation, so fix
+    // up the crossing points using their combined coverage.
+    let coverage = |family: Family, point| {
+        let (key, t) = family.coordinates(point);
+        runs.get(&(family, key))
+            .and_then(|runs| runs.iter().find(|run| (run.t0..=run.t1).contains(&t)))
+            .map_or(0, |run| run.count)
+    };
+    for point in crossings {
+        let counts = [
+            Family::Horizontal,
+            Family::Vertical,
+            Family::Diagonal,
+            Family::AntiDiagonal,
+        ]
+        .map(|family| coverage(family, point));
+
+        total -= counts.iter().filter(|&&c| c >= threshold).count();
+        if counts.iter().sum::<usize>() >= threshold {
+            total += 1;
+        }
+    }
+
+    Ok(total)
+}
+
+/// Counts the points covered by at least `threshold` of `lines` by visiting every
+/// point of every line.  This is only practical for short lines, but is useful
+/// for checking [`count_overlaps`].
+#[must_use]
+pub fn count_overlaps_rasterised(lines: &[Line], threshold: usize) -> usize {
+    let mut point_map = HashMap::new();
+    for line in lines {
+        for (x, y) in line.iter() {
+            *point_map.entry((x, y)).or_insert(0) += 1;
+        }
+    }
+
+    point_map.values().filter(|c| **c >= threshold).count()
 }
 
 impl FromProblemInputLine for Line {
@@ -75,26 +274,90 @@ impl Solution for Q5 {
         let mut lines: Vec<Line> = lines.parse();
         lines.retain(|l| !l.is_diagonal());
 
-        let mut point_map = HashMap::new();
-        for line in lines {
-            for (x, y) in line.iter() {
-                *point_map.entry((x, y)).or_insert(0) += 1;
-            }
-        }
-
-        point_map.values().filter(|c| **c >= 2).count().to_string()
+        count_overlaps(&lines, 2).unwrap().to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
         let lines: Vec<Line> = lines.parse();
 
-        let mut point_map = HashMap::new();
-        for line in lines {
-            for (x, y) in line.iter() {
-                *point_map.entry((x, y)).or_insert(0) += 1;
-            }
+        count_overlaps(&lines, 2).unwrap().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Random;
+
+    const EXAMPLE: [&str; 10] = [
+        "0,9 -> 5,9",
+        "8,0 -> 0,8",
+        "9,4 -> 3,4",
+        "2,2 -> 2,1",
+        "7,0 -> 7,4",
+        "6,4 -> 2,0",
+        "0,9 -> 2,9",
+        "3,4 -> 1,4",
+        "0,0 -> 8,8",
+        "5,5 -> 8,2",
+    ];
+
+    fn assert_matches_rasterised(lines: &[Line]) {
+        for threshold in 1..=4 {
+            assert_eq!(
+                count_overlaps(lines, threshold).unwrap(),
+                count_overlaps_rasterised(lines, threshold),
+                "threshold {} for {:?}",
+                threshold,
+                lines
+            );
         }
+    }
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q5.part1(&lines), "5");
+        assert_eq!(Q5.part2(&lines), "12");
+    }
+
+    #[test]
+    fn zero_length_lines_cover_one_point() {
+        let point = Line::new((3, 4), (3, 4));
+        assert_eq!(point.iter().collect::<Vec<_>>(), [(3, 4)]);
+
+        assert_matches_rasterised(&[
+            point,
+            point,
+            Line::new((0, 4), (5, 4)),
+            Line::new((0, 1), (6, 7)),
+            Line::new((2, 3), (2, 3)),
+            Line::new((5, 0), (0, 5)),
+        ]);
+    }
 
-        point_map.values().filter(|c| **c >= 2).count().to_string()
+    #[test]
+    fn sweep_matches_rasterised() {
+        let example: Vec<Line> = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        assert_matches_rasterised(&example);
+
+        // Random short lines in a small area, so that they overlap and cross a
+        // lot, in every direction including zero length.
+        let mut rng = Random::new(0x2021_0005);
+        let mut random = |n: i64| rng.below(n);
+
+        for _ in 0..200 {
+            let lines: Vec<Line> = (0..random(12) + 1)
+                .map(|_| {
+                    let src = (random(10), random(10));
+                    let (dx, dy) =
+                        [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (-1, -1)][random(6) as usize];
+                    let len = random(6);
+                    Line::new(src, (src.0 + dx * len, src.1 + dy * len))
+                })
+                .collect();
+
+            assert_matches_rasterised(&lines);
+        }
     }
 }
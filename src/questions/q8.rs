@@ -1,5 +1,5 @@
 use crate::{FromProblemInputLine, ProblemInput, Solution};
-use itertools::Itertools;
+use anyhow::{anyhow, bail, Result};
 use rayon::prelude::*;
 use std::collections::HashMap;
 
@@ -19,6 +19,186 @@ impl Disp {
             .chain(self.outputs.iter().cloned())
             .collect()
     }
+
+    /// Decodes the output words of this display using the given table of
+    /// segment patterns, returning the concatenated digits.
+    fn decode(&self, table: &HashMap<&str, &str>) -> Result<String> {
+        let wiring = solve_wiring(table, &self.words())?;
+        let digits = table
+            .iter()
+            .map(|(pattern, &digit)| Ok((mask(pattern)?, digit)))
+            .collect::<Result<HashMap<Mask, &str>>>()?;
+
+        self.outputs
+            .iter()
+            .map(|w| {
+                let segments = Mask(
+                    mask(w)?
+                        .bits()
+                        .fold(0, |acc, wire| acc | wiring[wire as usize]),
+                );
+                digits
+                    .get(&segments)
+                    .copied()
+                    .ok_or_else(|| anyhow!("{} decodes to unknown pattern {}", w, segments))
+            })
+            .collect()
+    }
+}
+
+/// A set of segments (or wires), with bit `i` standing for the letter `'a' + i`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Mask(u32);
+
+impl Mask {
+    fn bits(self) -> impl Iterator<Item = u32> {
+        (0..26).filter(move |i| self.0 & (1 << i) != 0)
+    }
+
+    fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+}
+
+impl std::fmt::Display for Mask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in self.bits() {
+            write!(f, "{}", char::from(b'a' + i as u8))?;
+        }
+        Ok(())
+    }
+}
+
+fn mask(w: &str) -> Result<Mask> {
+    w.chars().try_fold(Mask(0), |acc, c| {
+        if c.is_ascii_lowercase() {
+            Ok(Mask(acc.0 | 1 << (c as u32 - 'a' as u32)))
+        } else {
+            Err(anyhow!("invalid segment {} in {}", c, w))
+        }
+    })
+}
+
+/// Works out which segment each wire drives, returning a table indexed by wire
+/// which holds the (single bit) segment mask for that wire.
+///
+/// Every word must decode to a distinct pattern from `table`, so the segment
+/// count of a word limits the patterns it can be, and the number of wires two
+/// words share must match the number of segments their patterns share.  These
+/// constraints are propagated until every wire has a single possible segment.
+fn solve_wiring(table: &HashMap<&str, &str>, words: &[String]) -> Result<[u32; 26]> {
+    let patterns = table.keys().map(|p| mask(p)).collect::<Result<Vec<_>>>()?;
+    let alphabet = patterns.iter().fold(0, |acc, p| acc | p.0);
+
+    let mut words = words.iter().map(|w| mask(w)).collect::<Result<Vec<_>>>()?;
+    words.sort_by_key(|w| w.0);
+    words.dedup();
+    if let Some(w) = words.iter().find(|w| w.0 & !alphabet != 0) {
+        bail!("{} uses wires outside of {}", w, Mask(alphabet));
+    }
+
+    // The segments each wire could drive, and the patterns each word could be.
+    let mut domains = [0; 26];
+    for wire in Mask(alphabet).bits() {
+        domains[wire as usize] = alphabet;
+    }
+    let mut candidates: Vec<Vec<Mask>> = words
+        .iter()
+        .map(|w| {
+            patterns
+                .iter()
+                .copied()
+                .filter(|p| p.len() == w.len())
+                .collect()
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for i in 0..words.len() {
+            let word = words[i];
+            let wire_union = word
+                .bits()
+                .fold(0, |acc, wire| acc | domains[wire as usize]);
+
+            let before = candidates[i].len();
+            let mut kept = std::mem::take(&mut candidates[i]);
+            kept.retain(|p| {
+                p.0 & !wire_union == 0
+                    && word.bits().all(|wire| domains[wire as usize] & p.0 != 0)
+                    && (0..words.len()).filter(|&j| j != i).all(|j| {
+                        let shared = (word.0 & words[j].0).count_ones();
+                        candidates[j]
+                            .iter()
+                            .any(|q| q != p && (p.0 & q.0).count_ones() == shared)
+                    })
+            });
+            candidates[i] = kept;
+            changed |= candidates[i].len() != before;
+
+            if candidates[i].is_empty() {
+                bail!("no pattern matches {}", word);
+            }
+
+            // Wires in this word must drive a segment of one of its candidates,
+            // and wires outside it a segment missing from one of them.
+            let inside = candidates[i].iter().fold(0, |acc, p| acc | p.0);
+            let outside = candidates[i]
+                .iter()
+                .fold(0, |acc, p| acc | (!p.0 & alphabet));
+            for wire in Mask(alphabet).bits() {
+                let restrict = if word.0 & (1 << wire) != 0 {
+                    inside
+                } else {
+                    outside
+                };
+                let domain = &mut domains[wire as usize];
+                changed |= *domain & !restrict != 0;
+                *domain &= restrict;
+            }
+        }
+
+        // Distinct words are distinct patterns, and distinct wires are distinct
+        // segments.
+        for i in 0..words.len() {
+            if let [fixed] = candidates[i][..] {
+                for (j, c) in candidates.iter_mut().enumerate() {
+                    if j != i && c.contains(&fixed) {
+                        c.retain(|&p| p != fixed);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        for wire in Mask(alphabet).bits() {
+            let fixed = domains[wire as usize];
+            if fixed.count_ones() == 1 {
+                for other in Mask(alphabet).bits().filter(|&w| w != wire) {
+                    changed |= domains[other as usize] & fixed != 0;
+                    domains[other as usize] &= !fixed;
+                }
+            }
+        }
+
+        if let Some(wire) = Mask(alphabet).bits().find(|&w| domains[w as usize] == 0) {
+            bail!("no segment fits wire {}", Mask(1 << wire));
+        }
+    }
+
+    if let Some(wire) = Mask(alphabet)
+        .bits()
+        .find(|&w| domains[w as usize].count_ones() > 1)
+    {
+        bail!(
+            "wire {} is ambiguous between segments {}",
+            Mask(1 << wire),
+            Mask(domains[wire as usize])
+        );
+    }
+
+    Ok(domains)
 }
 
 impl FromProblemInputLine for Disp {
@@ -64,37 +244,86 @@ impl Solution for Q8 {
         let parts: Vec<Disp> = lines.parse();
         let digits = digits();
 
-        let is_valid_permutation = |words: &[String], p: &[char]| {
-            words
-                .iter()
-                .all(|w| digits.contains_key(permute_sort(w.as_str(), p).as_str()))
-        };
-
         parts
             .into_par_iter()
-            .map(|part| {
-                let words = part.words();
-
-                let permutation = ('a'..='g')
-                    .permutations(7)
-                    .find(|p| is_valid_permutation(&words, p))
-                    .unwrap();
-
-                part.outputs
-                    .iter()
-                    .map(|w| digits[permute_sort(w, &permutation).as_str()])
-                    .collect::<String>()
-                    .parse::<i64>()
-                    .unwrap()
-            })
-            .sum::<i64>()
+            .map(|part| -> Result<i64> { Ok(part.decode(digits)?.parse()?) })
+            .sum::<Result<i64>>()
+            .unwrap()
             .to_string()
     }
 }
 
-fn permute_sort(w: &str, permutation: &[char]) -> String {
-    w.chars()
-        .map(|c| permutation[(c as usize) - ('a' as usize)])
-        .sorted()
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 10] = [
+        "be cfbegad cbdgef fgaecd cgeb fdcge agebfd fecdb fabcd edb | fdgacbe cefdb cefbgd gcbe",
+        "edbfga begcd cbg gc gcadebf fbgde acbgfd abcde gfcbed gfec | fcgedb cgb dgebacf gc",
+        "fgaebd cg bdaec gdafb agbcfd gdcbef bgcad gfac gcb cdgabef | cg cg fdcagb cbg",
+        "fbegcd cbd adcefb dageb afcb bc aefdc ecdab fgdeca fcdbega | efabcd cedba gadfec cb",
+        "aecbfdg fbg gf bafeg dbefa fcge gcbea fcaegb dgceab fcbdga | gecf egdcabf bgf bfgea",
+        "fgeab ca afcebg bdacfeg cfaedg gcfdb baec bfadeg bafgc acf | gebdcfa ecba ca fadegcb",
+        "dbcfg fgd bdegcaf fgec aegbdf ecdfab fbedc dacgb gdcebf gf | cefg dcbef fcge gbcadfe",
+        "bdfegc cbegaf gecbf dfcage bdacg ed bedf ced adcbefg gebcd | ed bcgafe cdgba cbgef",
+        "egadfb cdbfeg cegd fecab cgb gbdefca cg fgcdab egfdb bfceg | gbdfcae bgc cg cgb",
+        "gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q8.part1(&lines), "26");
+        assert_eq!(Q8.part2(&lines), "61229");
+    }
+
+    #[test]
+    fn decodes_each_display() {
+        let disp = Disp::from_line(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        );
+        assert_eq!(disp.decode(digits()).unwrap(), "5353");
+
+        let decoded: Vec<String> = EXAMPLE
+            .iter()
+            .map(|line| Disp::from_line(line).decode(digits()).unwrap())
+            .collect();
+        assert_eq!(
+            decoded,
+            ["8394", "9781", "1197", "9361", "4873", "8418", "4548", "1625", "8717", "4315"]
+        );
+    }
+
+    #[test]
+    fn decodes_with_unsorted_custom_tables() {
+        // The usual digits, with their segments listed in a scrambled order and
+        // their values relabelled.
+        let table = HashMap::from([
+            ("gfecba", "zero"),
+            ("fc", "one"),
+            ("gedca", "two"),
+            ("cdgfa", "three"),
+            ("fcbd", "four"),
+            ("dbgfa", "five"),
+            ("fbeadg", "six"),
+            ("fca", "seven"),
+            ("gfedcba", "eight"),
+            ("cbagfd", "nine"),
+        ]);
+        let disp = Disp::from_line(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        );
+
+        assert_eq!(disp.decode(&table).unwrap(), "fivethreefivethree");
+    }
+
+    #[test]
+    fn rejects_words_that_fit_no_pattern() {
+        // Two different one-digit words can't both be a 1.
+        let disp = Disp::from_line("ab ac | ab");
+        assert!(disp.decode(digits()).is_err());
+
+        let disp = Disp::from_line("AB | AB");
+        assert!(disp.decode(digits()).is_err());
+    }
 }
@@ -1,80 +1,341 @@
-use crate::{FromProblemInput, NamedGraph, ProblemInput, Solution};
-use petgraph::stable_graph::StableGraph;
-use petgraph::visit::Dfs;
-use petgraph::Undirected;
+use crate::{FromProblemInput, ProblemInput, Solution};
 use std::collections::HashSet;
+use std::fmt;
 
 pub struct Q9;
 
-#[derive(Debug)]
-struct Grid(NamedGraph<i64, (), (usize, usize), Undirected>);
+/// The height a location must reach to be a wall between basins, in the puzzle.
+pub const WALL_HEIGHT: i64 = 9;
 
-impl FromProblemInput<'_> for Grid {
-    fn from(lines: &ProblemInput) -> Self {
-        let mut graph = NamedGraph::new();
-
-        for (y, line) in lines.iter().enumerate() {
-            for (x, c) in line.chars().enumerate() {
-                let value = c.to_digit(10).unwrap() as i64;
-                graph.insert((y, x), value);
-                if x > 0 && graph.contains(&(y, x - 1)) {
-                    graph.insert_edge(&(y, x), &(y, x - 1), ());
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    width: usize,
+    height: usize,
+    /// Heights in row-major order.
+    heights: Vec<i64>,
+}
+
+impl Heightmap {
+    /// Creates a heightmap from heights given in row-major order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `heights` doesn't have `width * height` entries.
+    #[must_use]
+    pub fn new(width: usize, height: usize, heights: Vec<i64>) -> Self {
+        assert_eq!(
+            heights.len(),
+            width * height,
+            "heightmap must be rectangular"
+        );
+
+        Self {
+            width,
+            height,
+            heights,
+        }
+    }
+
+    fn position(&self, index: usize) -> (usize, usize) {
+        (index % self.width, index / self.width)
+    }
+
+    /// Returns the locations up, down, left and right of `index`.
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (x, y) = self.position(index);
+        let width = self.width;
+
+        [
+            (x > 0).then(|| index - 1),
+            (x + 1 < self.width).then(|| index + 1),
+            (y > 0).then(|| index - width),
+            (y + 1 < self.height).then(|| index + width),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Returns the locations, as `(x, y)` in reading order, which are strictly
+    /// lower than every neighbour.  A plateau has no low points, since its
+    /// locations are only as low as each other.
+    #[must_use]
+    pub fn low_points(&self) -> Vec<(usize, usize)> {
+        (0..self.heights.len())
+            .filter(|&index| {
+                self.neighbours(index)
+                    .all(|n| self.heights[n] > self.heights[index])
+            })
+            .map(|index| self.position(index))
+            .collect()
+    }
+
+    /// The risk level of the location at `(x, y)`.
+    #[must_use]
+    pub fn risk_level(&self, x: usize, y: usize) -> i64 {
+        self.heights[y * self.width + x] + 1
+    }
+
+    /// Splits the locations lower than `wall` into basins.
+    ///
+    /// Water flows from each location to its lowest neighbour, as long as that's
+    /// lower still, and each basin is everything which flows to the same low
+    /// point.  Neighbouring locations of the same height form a plateau, which
+    /// is treated as a single location: water flows off it to the lowest of its
+    /// neighbours, and if none of them are lower, the whole plateau is the low
+    /// point of its basin.
+    #[must_use]
+    pub fn basins(&self, wall: i64) -> Basins {
+        let size = self.heights.len();
+        let open = |index: usize| self.heights[index] < wall;
+
+        // Number the plateaus in reading order of their first location.
+        let mut plateau = vec![None; size];
+        let mut plateaus: Vec<Vec<usize>> = Vec::new();
+        for start in (0..size).filter(|&i| open(i)) {
+            if plateau[start].is_some() {
+                continue;
+            }
+
+            let id = plateaus.len();
+            let mut cells = vec![start];
+            plateau[start] = Some(id);
+            let mut next = 0;
+            while let Some(&index) = cells.get(next) {
+                next += 1;
+                for neighbour in self.neighbours(index) {
+                    if plateau[neighbour].is_none()
+                        && self.heights[neighbour] == self.heights[start]
+                    {
+                        plateau[neighbour] = Some(id);
+                        cells.push(neighbour);
+                    }
+                }
+            }
+            cells.sort_unstable();
+            plateaus.push(cells);
+        }
+
+        // Work out where each plateau drains to, preferring the lowest neighbour
+        // and then the first in reading order.
+        let drains_to: Vec<Option<usize>> = plateaus
+            .iter()
+            .map(|cells| {
+                let height = self.heights[cells[0]];
+                cells
+                    .iter()
+                    .flat_map(|&index| self.neighbours(index))
+                    .filter(|&n| open(n) && self.heights[n] < height)
+                    .min_by_key(|&n| (self.heights[n], n))
+                    .map(|n| plateau[n].unwrap())
+            })
+            .collect();
+
+        // Follow each plateau downhill to its low point.  Heights strictly fall
+        // along the way, so this always ends.
+        let mut sink = vec![None; plateaus.len()];
+        for id in 0..plateaus.len() {
+            let mut path = vec![id];
+            let mut current = id;
+            let low = loop {
+                if let Some(low) = sink[current] {
+                    break low;
                 }
-                if y > 0 && graph.contains(&(y - 1, x)) {
-                    graph.insert_edge(&(y, x), &(y - 1, x), ());
+                match drains_to[current] {
+                    Some(next) => {
+                        path.push(next);
+                        current = next;
+                    }
+                    None => break current,
                 }
+            };
+            for id in path {
+                sink[id] = Some(low);
+            }
+        }
+
+        let mut basin_of = vec![None; plateaus.len()];
+        let mut basins = Vec::new();
+        for (id, cells) in plateaus.iter().enumerate() {
+            if drains_to[id].is_none() {
+                basin_of[id] = Some(basins.len());
+                basins.push(Basin {
+                    low_points: cells.iter().map(|&i| self.position(i)).collect(),
+                    height: self.heights[cells[0]],
+                    cells: Vec::new(),
+                });
+            }
+        }
+
+        let mut labels = vec![None; size];
+        for (index, label) in labels.iter_mut().enumerate() {
+            if let Some(id) = plateau[index] {
+                let basin = basin_of[sink[id].unwrap()].unwrap();
+                basins[basin].cells.push(self.position(index));
+                *label = Some(basin);
             }
         }
 
-        Grid(graph)
+        Basins {
+            width: self.width,
+            labels,
+            basins,
+        }
     }
 }
 
-#[must_use]
-pub fn connected_components<N, E>(g: &StableGraph<N, E, Undirected>) -> Vec<usize> {
-    let mut visited = HashSet::new();
-    let mut components = Vec::new();
-    for node in g.node_indices() {
-        if visited.contains(&node) {
-            continue;
-        }
-        let mut dfs = Dfs::new(g, node);
-        let mut component = 0;
-        while let Some(v) = dfs.next(g) {
-            visited.insert(v);
-            component += 1;
+impl FromProblemInput<'_> for Heightmap {
+    fn from(lines: &ProblemInput) -> Self {
+        let width = lines.lines[0].len();
+        let heights = lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .map(|c| c.to_digit(10).unwrap() as i64)
+            .collect();
+
+        Heightmap::new(width, lines.len(), heights)
+    }
+}
+
+/// The locations which drain to a single low point.  Locations are given as
+/// `(x, y)`, in reading order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Basin {
+    /// The lowest locations in the basin, of which there's more than one if the
+    /// bottom is a plateau.
+    pub low_points: Vec<(usize, usize)>,
+    /// The height of the low points.
+    pub height: i64,
+    /// Every location in the basin, including the low points.
+    pub cells: Vec<(usize, usize)>,
+}
+
+impl Basin {
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The first of the basin's low points.
+    #[must_use]
+    pub fn low_point(&self) -> (usize, usize) {
+        self.low_points[0]
+    }
+}
+
+/// A heightmap split into basins.
+#[derive(Debug, Clone)]
+pub struct Basins {
+    width: usize,
+    /// The basin containing each location in row-major order, or `None` for
+    /// walls.
+    labels: Vec<Option<usize>>,
+    /// The basins in reading order of their first low point.
+    pub basins: Vec<Basin>,
+}
+
+impl Basins {
+    /// The index of the basin containing `(x, y)`, or `None` if it's a wall.
+    #[must_use]
+    pub fn basin_at(&self, x: usize, y: usize) -> Option<usize> {
+        self.labels[y * self.width + x]
+    }
+}
+
+impl fmt::Display for Basins {
+    /// Draws a map of the basins, with walls as `#`, low points as `*`, and the
+    /// rest of each basin labelled with a letter.  Letters are handed out in
+    /// order and reused every 26 basins, so two basins can share a letter, even
+    /// if they're next to each other.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let low_points: HashSet<_> = self
+            .basins
+            .iter()
+            .flat_map(|basin| &basin.low_points)
+            .collect();
+
+        for (y, row) in self.labels.chunks(self.width).enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for (x, label) in row.iter().enumerate() {
+                let c = match label {
+                    None => '#',
+                    Some(_) if low_points.contains(&(x, y)) => '*',
+                    Some(basin) => (b'a' + (basin % 26) as u8) as char,
+                };
+                write!(f, "{}", c)?;
+            }
         }
-        components.push(component);
+
+        Ok(())
     }
-    components
 }
 
 impl Solution for Q9 {
     fn part1(&self, lines: &ProblemInput) -> String {
-        let grid: Grid = lines.parse();
-
-        let mut s = 0;
-        for (_, index, &value) in grid.0.nodes_iter() {
-            if grid
-                .0
-                .graph
-                .neighbors_undirected(index)
-                .all(|n| *grid.0.graph.node_weight(n).unwrap() > value)
-            {
-                s += 1 + value;
-            }
-        }
+        let heightmap: Heightmap = lines.parse();
 
-        s.to_string()
+        heightmap
+            .low_points()
+            .into_iter()
+            .map(|(x, y)| heightmap.risk_level(x, y))
+            .sum::<i64>()
+            .to_string()
     }
 
     fn part2(&self, lines: &ProblemInput) -> String {
-        let mut grid: Grid = lines.parse();
-        grid.0.retain_nodes(|_, _, weight| *weight != 9);
+        let heightmap: Heightmap = lines.parse();
+        let basins = heightmap.basins(WALL_HEIGHT);
+
+        let mut sizes: Vec<usize> = basins.basins.iter().map(Basin::size).collect();
+        sizes.sort_by_key(|&x| std::cmp::Reverse(x));
+
+        sizes[0..3].iter().product::<usize>().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [&str; 5] = [
+        "2199943210",
+        "3987894921",
+        "9856789892",
+        "8767896789",
+        "9899965678",
+    ];
+
+    #[test]
+    fn example() {
+        let lines = ProblemInput::from(EXAMPLE.to_vec());
+        assert_eq!(Q9.part1(&lines), "15");
+        assert_eq!(Q9.part2(&lines), "1134");
+    }
+
+    #[test]
+    fn example_basins() {
+        let heightmap: Heightmap = ProblemInput::from(EXAMPLE.to_vec()).parse();
+        let basins = heightmap.basins(WALL_HEIGHT);
+
+        let sizes: Vec<usize> = basins.basins.iter().map(Basin::size).collect();
+        assert_eq!(sizes, [3, 9, 14, 9]);
+        assert_eq!(basins.basin_at(0, 0), Some(0));
+        assert_eq!(basins.basin_at(2, 0), None);
+        assert_eq!(
+            basins.to_string(),
+            "a*###bbbb*\na#ccc#b#bb\n#c*ccc#d#b\nccccc#ddd#\n#c###d*ddd"
+        );
+    }
 
-        let mut components = connected_components(&grid.0.graph);
-        components.sort_by_key(|&x| std::cmp::Reverse(x));
+    #[test]
+    fn plateaus_are_basins_but_not_low_points() {
+        let heightmap = Heightmap::new(3, 2, vec![1, 1, 9, 2, 9, 9]);
+        assert!(heightmap.low_points().is_empty());
 
-        components[0..3].iter().fold(1, |l, r| l * *r).to_string()
+        let basins = heightmap.basins(WALL_HEIGHT);
+        assert_eq!(basins.basins.len(), 1);
+        assert_eq!(basins.basins[0].low_points, [(0, 0), (1, 0)]);
+        assert_eq!(basins.basins[0].size(), 3);
     }
 }
@@ -0,0 +1,368 @@
+//! Positions, rotations and boxes in three dimensions.
+
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use std::ops::{Add, Neg, Sub};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct Position3 {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl Position3 {
+    #[must_use]
+    pub fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    #[must_use]
+    pub fn dot(self, other: Self) -> i64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    #[must_use]
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    /// The sum of the absolute differences along each axis.
+    #[must_use]
+    pub fn manhattan(self, other: Self) -> i64 {
+        let d = self - other;
+        d.x.abs() + d.y.abs() + d.z.abs()
+    }
+
+    /// The largest absolute difference along any axis.
+    #[must_use]
+    pub fn chebyshev(self, other: Self) -> i64 {
+        let d = self - other;
+        d.x.abs().max(d.y.abs()).max(d.z.abs())
+    }
+
+    /// The square of the straight-line distance, which unlike the distance
+    /// itself is always an integer.
+    #[must_use]
+    pub fn distance_squared(self, other: Self) -> i64 {
+        let d = self - other;
+        d.dot(d)
+    }
+
+    fn to_array(self) -> [i64; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl Add for Position3 {
+    type Output = Position3;
+
+    fn add(self, other: Position3) -> Position3 {
+        Position3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Position3 {
+    type Output = Position3;
+
+    fn sub(self, other: Position3) -> Position3 {
+        Position3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Neg for Position3 {
+    type Output = Position3;
+
+    fn neg(self) -> Position3 {
+        Position3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// The rows of a rotation matrix.
+type Matrix = [[i64; 3]; 3];
+
+/// The 24 rotations, with the tables needed to compose and invert them.
+struct Group {
+    matrices: Vec<Matrix>,
+    /// `compose[a][b]` is the rotation applying `b` and then `a`.
+    compose: Vec<Vec<u8>>,
+    inverse: Vec<u8>,
+}
+
+impl Group {
+    fn get() -> &'static Group {
+        static GROUP: OnceCell<Group> = OnceCell::new();
+        GROUP.get_or_init(Group::new)
+    }
+
+    fn new() -> Self {
+        let axes = [
+            Position3::new(1, 0, 0),
+            Position3::new(0, 1, 0),
+            Position3::new(0, 0, 1),
+            Position3::new(-1, 0, 0),
+            Position3::new(0, -1, 0),
+            Position3::new(0, 0, -1),
+        ];
+
+        // Send x and y to any two perpendicular axes; z must then go to their
+        // cross product for the rotation not to be a reflection.
+        let matrices: Vec<Matrix> = axes
+            .into_iter()
+            .permutations(2)
+            .filter(|p| p[0].dot(p[1]) == 0)
+            .map(|p| {
+                let columns = [p[0], p[1], p[0].cross(p[1])].map(Position3::to_array);
+                [0, 1, 2].map(|row| columns.map(|column| column[row]))
+            })
+            .collect();
+
+        let index = |matrix: &Matrix| matrices.iter().position(|m| m == matrix).unwrap() as u8;
+        let multiply = |a: &Matrix, b: &Matrix| {
+            [0, 1, 2].map(|row| [0, 1, 2].map(|col| (0..3).map(|k| a[row][k] * b[k][col]).sum()))
+        };
+
+        let compose = matrices
+            .iter()
+            .map(|a| matrices.iter().map(|b| index(&multiply(a, b))).collect())
+            .collect();
+        let inverse = matrices
+            .iter()
+            .map(|m| index(&[0, 1, 2].map(|row| [0, 1, 2].map(|col| m[col][row]))))
+            .collect();
+
+        Self {
+            matrices,
+            compose,
+            inverse,
+        }
+    }
+}
+
+/// One of the 24 rotations which take the axes onto each other.  The rotations
+/// are computed once and shared, so copying, composing and inverting them are
+/// all just lookups.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rotation(u8);
+
+impl Rotation {
+    #[must_use]
+    pub fn identity() -> Self {
+        // The identity is always generated first.
+        Rotation(0)
+    }
+
+    /// Every rotation, starting with the identity.
+    pub fn all() -> impl Iterator<Item = Rotation> + Clone {
+        (0..24).map(Rotation)
+    }
+
+    /// The rows of this rotation's matrix.
+    #[must_use]
+    pub fn matrix(self) -> [[i64; 3]; 3] {
+        Group::get().matrices[self.0 as usize]
+    }
+
+    #[must_use]
+    pub fn apply(self, p: Position3) -> Position3 {
+        let [x, y, z] = self
+            .matrix()
+            .map(|row| Position3::new(row[0], row[1], row[2]).dot(p));
+        Position3::new(x, y, z)
+    }
+
+    /// The rotation which applies `other` and then this one.
+    #[must_use]
+    pub fn compose(self, other: Rotation) -> Rotation {
+        Rotation(Group::get().compose[self.0 as usize][other.0 as usize])
+    }
+
+    /// The rotation which undoes this one.
+    #[must_use]
+    pub fn inverse(self) -> Rotation {
+        Rotation(Group::get().inverse[self.0 as usize])
+    }
+}
+
+/// The smallest box, aligned with the axes, containing some positions.  Both
+/// corners are included in the box.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BoundingBox {
+    pub min: Position3,
+    pub max: Position3,
+}
+
+impl BoundingBox {
+    /// Finds the box around `points`, or `None` if there aren't any.
+    pub fn from_points<I: IntoIterator<Item = Position3>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        let mut bounds = Self {
+            min: first,
+            max: first,
+        };
+        for p in points {
+            bounds.extend(p);
+        }
+
+        Some(bounds)
+    }
+
+    /// Grows this box to contain `p`.
+    pub fn extend(&mut self, p: Position3) {
+        self.min = Position3::new(
+            self.min.x.min(p.x),
+            self.min.y.min(p.y),
+            self.min.z.min(p.z),
+        );
+        self.max = Position3::new(
+            self.max.x.max(p.x),
+            self.max.y.max(p.y),
+            self.max.z.max(p.z),
+        );
+    }
+
+    #[must_use]
+    pub fn contains(&self, p: Position3) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x)
+            && (self.min.y..=self.max.y).contains(&p.y)
+            && (self.min.z..=self.max.z).contains(&p.z)
+    }
+
+    /// The number of positions along each axis.
+    #[must_use]
+    pub fn size(&self) -> Position3 {
+        self.max - self.min + Position3::new(1, 1, 1)
+    }
+
+    /// The number of positions in the box.
+    #[must_use]
+    pub fn volume(&self) -> i64 {
+        let size = self.size();
+        size.x * size.y * size.z
+    }
+
+    /// The positions in both boxes, or `None` if they don't overlap.
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let min = Position3::new(
+            self.min.x.max(other.min.x),
+            self.min.y.max(other.min.y),
+            self.min.z.max(other.min.z),
+        );
+        let max = Position3::new(
+            self.max.x.min(other.max.x),
+            self.max.y.min(other.max.y),
+            self.max.z.min(other.max.z),
+        );
+
+        if min.x <= max.x && min.y <= max.y && min.z <= max.z {
+            Some(Self { min, max })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn distances() {
+        let (a, b) = (Position3::new(1, -2, 3), Position3::new(-3, 4, 5));
+
+        assert_eq!(a.manhattan(b), 12);
+        assert_eq!(a.chebyshev(b), 6);
+        assert_eq!(a.distance_squared(b), 56);
+        assert_eq!(a - b + b, a);
+        assert_eq!(-a, Position3::new(-1, 2, -3));
+        assert_eq!(
+            Position3::new(1, 0, 0).cross(Position3::new(0, 1, 0)),
+            Position3::new(0, 0, 1)
+        );
+    }
+
+    #[test]
+    fn there_are_24_distinct_rotations() {
+        let v = Position3::new(1, 2, 3);
+        let images: HashSet<Position3> = Rotation::all().map(|r| r.apply(v)).collect();
+
+        assert_eq!(Rotation::all().count(), 24);
+        assert_eq!(images.len(), 24);
+        assert_eq!(Rotation::identity().apply(v), v);
+        assert_eq!(Rotation::all().next(), Some(Rotation::identity()));
+
+        // Every rotation keeps distances and handedness.
+        for r in Rotation::all() {
+            let [x, y, z] = [
+                Position3::new(1, 0, 0),
+                Position3::new(0, 1, 0),
+                Position3::new(0, 0, 1),
+            ]
+            .map(|axis| r.apply(axis));
+            assert_eq!(x.cross(y), z);
+            assert_eq!(r.apply(v).distance_squared(Position3::default()), 14);
+        }
+    }
+
+    #[test]
+    fn rotations_form_a_group() {
+        let v = Position3::new(5, -7, 11);
+
+        for a in Rotation::all() {
+            assert_eq!(a.compose(a.inverse()), Rotation::identity());
+            assert_eq!(a.inverse().compose(a), Rotation::identity());
+
+            for b in Rotation::all() {
+                assert_eq!(a.compose(b).apply(v), a.apply(b.apply(v)));
+            }
+        }
+
+        let products: HashSet<Rotation> = Rotation::all()
+            .map(|b| Rotation::all().nth(7).unwrap().compose(b))
+            .collect();
+        assert_eq!(products.len(), 24);
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let bounds = BoundingBox::from_points([
+            Position3::new(0, 5, -1),
+            Position3::new(2, 3, 4),
+            Position3::new(1, 1, 1),
+        ])
+        .unwrap();
+
+        assert_eq!(bounds.min, Position3::new(0, 1, -1));
+        assert_eq!(bounds.max, Position3::new(2, 5, 4));
+        assert_eq!(bounds.size(), Position3::new(3, 5, 6));
+        assert_eq!(bounds.volume(), 90);
+        assert!(bounds.contains(Position3::new(2, 1, -1)));
+        assert!(!bounds.contains(Position3::new(3, 1, -1)));
+        assert_eq!(BoundingBox::from_points(Vec::new()), None);
+
+        let other = BoundingBox {
+            min: Position3::new(2, 0, 0),
+            max: Position3::new(9, 9, 9),
+        };
+        assert_eq!(
+            bounds.intersection(&other),
+            Some(BoundingBox {
+                min: Position3::new(2, 1, 0),
+                max: Position3::new(2, 5, 4),
+            })
+        );
+        let apart = BoundingBox {
+            min: Position3::new(3, 0, 0),
+            max: Position3::new(9, 9, 9),
+        };
+        assert_eq!(bounds.intersection(&apart), None);
+    }
+}
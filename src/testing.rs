@@ -0,0 +1,35 @@
+//! Helpers shared by the unit tests.
+
+/// A seeded pseudo-random number generator, so that randomised tests check the
+/// same cases on every run.
+#[derive(Clone, Debug)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        // Knuth's MMIX constants; the high bits are the most random.
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.0 >> 33
+    }
+
+    /// Returns a value in `0..n`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` isn't positive.
+    pub fn below(&mut self, n: i64) -> i64 {
+        assert!(n > 0, "there are no values below {}", n);
+        (self.next() % n as u64) as i64
+    }
+
+    pub fn coin(&mut self) -> bool {
+        self.next() & 1 == 1
+    }
+}
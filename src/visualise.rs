@@ -0,0 +1,258 @@
+//! Watching solutions work.
+//!
+//! A solution which has something worth watching draws frames while it runs and
+//! hands them to a [`Sink`], which might show them in the terminal, write them
+//! to files, or throw them away.  Frames are drawn through `show_with`, which
+//! skips drawing them when nobody is watching.
+
+use crate::grid::PointSet;
+use anyhow::{Context, Error, Result};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// A snapshot of a solution's working.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// A picture that's already been drawn.
+    Text(String),
+    /// Points to be drawn within their bounding box, with `y` increasing
+    /// downwards.
+    Points(PointSet),
+}
+
+impl Frame {
+    #[must_use]
+    pub fn render(&self) -> String {
+        match self {
+            Frame::Text(text) => text.clone(),
+            Frame::Points(points) => points.render('#', '.'),
+        }
+    }
+}
+
+impl From<String> for Frame {
+    fn from(text: String) -> Self {
+        Frame::Text(text)
+    }
+}
+
+impl From<PointSet> for Frame {
+    fn from(points: PointSet) -> Self {
+        Frame::Points(points)
+    }
+}
+
+/// Somewhere to send frames.
+pub trait Sink: Send + Sync {
+    fn show(&self, frame: Frame);
+
+    /// Whether frames sent here go anywhere, so that they're worth drawing.
+    fn wants_frames(&self) -> bool {
+        true
+    }
+}
+
+impl dyn Sink + '_ {
+    /// Draws a frame and shows it, but only if anything wants it.
+    pub fn show_with<F: FnOnce() -> Frame>(&self, draw: F) {
+        if self.wants_frames() {
+            self.show(draw());
+        }
+    }
+}
+
+/// Throws every frame away.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Discard;
+
+impl Sink for Discard {
+    fn show(&self, _frame: Frame) {}
+
+    fn wants_frames(&self) -> bool {
+        false
+    }
+}
+
+/// Keeps every frame in memory.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    frames: Mutex<Vec<Frame>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the frames shown so far, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread panicked while showing a frame.
+    #[must_use]
+    pub fn into_frames(self) -> Vec<Frame> {
+        self.frames.into_inner().unwrap()
+    }
+}
+
+impl Sink for Recorder {
+    fn show(&self, frame: Frame) {
+        self.frames.lock().unwrap().push(frame);
+    }
+}
+
+/// Plays frames in the terminal as they arrive, redrawing the screen for each
+/// one and then pausing.  Every player draws over the whole terminal, so only
+/// one should be playing at a time.
+#[derive(Debug)]
+pub struct Player {
+    title: String,
+    delay: Duration,
+    count: AtomicUsize,
+}
+
+impl Player {
+    /// Creates a player which shows `title` above each frame and pauses for
+    /// `delay` after it.
+    #[must_use]
+    pub fn new(title: impl Into<String>, delay: Duration) -> Self {
+        Self {
+            title: title.into(),
+            delay,
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Sink for Player {
+    fn show(&self, frame: Frame) {
+        let index = self.count.fetch_add(1, Ordering::Relaxed);
+
+        // Clear the screen and move the cursor back to the top left.
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = write!(
+            stdout,
+            "\x1B[2J\x1B[H{} (frame {})\n\n{}\n",
+            self.title,
+            index,
+            frame.render()
+        );
+        let _ = stdout.flush();
+        drop(stdout);
+
+        thread::sleep(self.delay);
+    }
+}
+
+/// Writes each frame to its own numbered file in a directory.
+#[derive(Debug)]
+pub struct Dump {
+    dir: PathBuf,
+    prefix: String,
+    count: AtomicUsize,
+    /// The first error hit while writing, which is reported by `finish`.
+    error: Mutex<Option<Error>>,
+}
+
+impl Dump {
+    /// Creates a sink writing frames to `dir` as `<prefix>-00000.txt`,
+    /// `<prefix>-00001.txt` and so on, creating the directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be created.
+    pub fn new<P: AsRef<Path>>(dir: P, prefix: impl Into<String>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create frame directory {}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            prefix: prefix.into(),
+            count: AtomicUsize::new(0),
+            error: Mutex::new(None),
+        })
+    }
+
+    /// Returns the number of frames written.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error hit while writing a frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread panicked while showing a frame.
+    pub fn finish(self) -> Result<usize> {
+        match self.error.into_inner().unwrap() {
+            Some(error) => Err(error),
+            None => Ok(self.count.into_inner()),
+        }
+    }
+}
+
+impl Sink for Dump {
+    fn show(&self, frame: Frame) {
+        let index = self.count.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}-{:05}.txt", self.prefix, index));
+
+        if let Err(error) = fs::write(&path, frame.render() + "\n") {
+            let mut first = self.error.lock().unwrap();
+            if first.is_none() {
+                *first =
+                    Some(Error::new(error).context(format!("failed to write {}", path.display())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Position;
+
+    #[test]
+    fn discarded_frames_are_never_drawn() {
+        let sink: &dyn Sink = &Discard;
+        sink.show_with(|| panic!("nobody is watching"));
+    }
+
+    #[test]
+    fn recorder_keeps_frames_in_order() {
+        let recorder = Recorder::new();
+        let sink: &dyn Sink = &recorder;
+        sink.show_with(|| Frame::Text("first".to_string()));
+        sink.show_with(|| {
+            Frame::Points(
+                [Position::new(0, 0), Position::new(2, 1)]
+                    .into_iter()
+                    .collect(),
+            )
+        });
+
+        let frames: Vec<String> = recorder.into_frames().iter().map(Frame::render).collect();
+        assert_eq!(frames, ["first", "#..\n..#"]);
+    }
+
+    #[test]
+    fn dump_writes_numbered_files() {
+        let dir = std::env::temp_dir().join(format!("aoc2021-frames-{}", std::process::id()));
+        let dump = Dump::new(&dir, "part1").unwrap();
+        dump.show(Frame::Text("a".to_string()));
+        dump.show(Frame::Text("b".to_string()));
+
+        assert_eq!(dump.finish().unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join("part1-00001.txt")).unwrap(),
+            "b\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+/// An iterator over the sums of every window of `width` consecutive values from
+/// an underlying iterator.  Only the current window is kept in memory.
+#[derive(Debug, Clone)]
+pub struct WindowSums<I> {
+    iter: I,
+    width: usize,
+    window: VecDeque<i64>,
+    sum: i64,
+}
+
+impl<I: Iterator<Item = i64>> Iterator for WindowSums<I> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for value in self.iter.by_ref() {
+            self.window.push_back(value);
+            self.sum += value;
+
+            if self.window.len() > self.width {
+                self.sum -= self.window.pop_front().unwrap();
+            }
+            if self.window.len() == self.width {
+                return Some(self.sum);
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns an iterator over the sums of every window of `width` consecutive
+/// values.
+///
+/// # Panics
+///
+/// Panics if `width` is zero.
+pub fn window_sums<I: IntoIterator<Item = i64>>(
+    values: I,
+    width: usize,
+) -> WindowSums<I::IntoIter> {
+    assert!(width > 0, "windows must have a non-zero width");
+
+    WindowSums {
+        iter: values.into_iter(),
+        width,
+        window: VecDeque::with_capacity(width + 1),
+        sum: 0,
+    }
+}
+
+/// Counts the pairs of windows of `width` consecutive values, starting `stride`
+/// values apart, for which `predicate(earlier_sum, later_sum)` holds.
+///
+/// # Panics
+///
+/// Panics if `width` or `stride` is zero.
+pub fn count_window_comparisons<I, F>(
+    values: I,
+    width: usize,
+    stride: usize,
+    mut predicate: F,
+) -> usize
+where
+    I: IntoIterator<Item = i64>,
+    F: FnMut(i64, i64) -> bool,
+{
+    assert!(stride > 0, "windows must have a non-zero stride");
+
+    // The sums of the last `stride` windows, oldest first.
+    let mut previous = VecDeque::with_capacity(stride + 1);
+    let mut count = 0;
+
+    for sum in window_sums(values, width) {
+        previous.push_back(sum);
+        if previous.len() > stride {
+            let earlier = previous.pop_front().unwrap();
+            if predicate(earlier, sum) {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+/// Counts how many times the sum of a window of `width` consecutive values is
+/// larger than the sum of the window before it.
+///
+/// # Panics
+///
+/// Panics if `width` is zero.
+pub fn count_window_increases<I: IntoIterator<Item = i64>>(values: I, width: usize) -> usize {
+    count_window_comparisons(values, width, 1, |earlier, later| later > earlier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPTHS: [i64; 10] = [199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+
+    #[test]
+    fn sums_each_full_window() {
+        assert_eq!(window_sums([1, 2, 3, 4], 2).collect::<Vec<_>>(), [3, 5, 7]);
+        assert_eq!(window_sums([1, 2, 3, 4], 4).collect::<Vec<_>>(), [10]);
+        assert_eq!(window_sums([1, 2, 3, 4], 5).count(), 0);
+        assert_eq!(
+            window_sums(DEPTHS, 3).collect::<Vec<_>>(),
+            [607, 618, 618, 617, 647, 716, 769, 792]
+        );
+    }
+
+    #[test]
+    fn counts_increases() {
+        assert_eq!(count_window_increases(DEPTHS, 1), 7);
+        assert_eq!(count_window_increases(DEPTHS, 3), 5);
+        assert_eq!(count_window_increases(DEPTHS, 20), 0);
+    }
+
+    #[test]
+    fn strides_and_predicates() {
+        // Windows of three overlap in all but their ends, so comparing them is
+        // the same as comparing single values three apart.
+        assert_eq!(count_window_comparisons(DEPTHS, 1, 3, |a, b| b > a), 5);
+        assert_eq!(count_window_comparisons(DEPTHS, 1, 1, |a, b| b < a), 2);
+        assert_eq!(count_window_comparisons(DEPTHS, 3, 1, |a, b| a == b), 1);
+        assert_eq!(count_window_comparisons(DEPTHS, 2, 9, |_, _| true), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-zero width")]
+    fn zero_width_is_rejected() {
+        let _ = window_sums(DEPTHS, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-zero stride")]
+    fn zero_stride_is_rejected() {
+        count_window_comparisons(DEPTHS, 1, 0, |_, _| true);
+    }
+}